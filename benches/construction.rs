@@ -0,0 +1,54 @@
+use const_regex_regex_transformer::automata::{try_to_dfa, try_to_nfa};
+use const_regex_regex_transformer::options::{CompileOptions, Construction};
+use const_regex_regex_transformer::simplify::simplify;
+use const_regex_regex_transformer::derivative::try_regex_to_dfa;
+use const_regex_regex_transformer::to_regex;
+use std::time::{Duration, Instant};
+
+const RUNS: u32 = 20;
+
+fn main() {
+    let keywords = (0..300).map(|i| format!("keyword{i}")).collect::<Vec<_>>().join("|");
+
+    let patterns = [
+        "hell[a-z]+o+",
+        "[a-z0-9._%+-]+@[a-z0-9.-]+\\.[a-z]{2,6}",
+        "(a|b|c|d|e)*(ab|cd)+[0-9]{1,50}",
+        "((x|y)?z*)+w",
+        "[ab]*a[ab]{8}",
+        &keywords,
+    ];
+
+    println!("{:<16} {:<10} {:>10} {:>10} {:>14}", "Pattern", "NFA", "NFA states", "DFA states", "Compile time");
+    for pattern in patterns {
+        let name = if pattern.len() > 16 { &pattern[..13] } else { pattern };
+        for construction in [Construction::Thompson, Construction::Glushkov, Construction::Brzozowski] {
+            let options = CompileOptions { construction, ..CompileOptions::default() };
+
+            let mut total = Duration::ZERO;
+            let mut sizes = (0, 0);
+            for _ in 0..RUNS {
+                let start = Instant::now();
+                let regex = simplify(to_regex(pattern));
+                let (nfa_states, dfa) = if construction == Construction::Brzozowski {
+                    (0, try_regex_to_dfa(&regex, &options).unwrap())
+                }
+                else {
+                    let nfa = try_to_nfa(regex, &options).unwrap();
+                    (nfa.state_count(), try_to_dfa(nfa, &options).unwrap())
+                };
+                total += start.elapsed();
+                sizes = (nfa_states, dfa.transitions.len());
+            }
+
+            println!(
+                "{:<16} {:<10} {:>10} {:>10} {:>14?}",
+                if construction == Construction::Thompson { name } else { "" },
+                format!("{construction:?}"),
+                sizes.0,
+                sizes.1,
+                total / RUNS
+            );
+        }
+    }
+}
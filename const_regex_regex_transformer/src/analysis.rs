@@ -0,0 +1,213 @@
+use crate::automata::{TransitionType, DFA};
+use itertools::Itertools;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, VecDeque};
+
+impl DFA {
+    /// `counts[l][s]` is the number of strings of length `l` accepted starting from state `s`, for every `l` up to
+    /// `max_len`. Counts saturate at `u128::MAX`.
+    pub(crate) fn path_counts(&self, max_len: usize) -> Vec<Vec<u128>> {
+        let mut counts = vec![self.transitions.iter().map(|(s, _)| *s as u128).collect_vec()];
+        for l in 1..=max_len {
+            let row = self.transitions.iter().map(|(_, ts)| {
+                ts.iter().fold(0u128, |total, (t, ns)| {
+                    let chars = t.codepoints().into_iter().map(|(a, b)| char_count(a, b)).sum::<u128>();
+                    total.saturating_add(chars.saturating_mul(counts[l - 1][*ns]))
+                })
+            }).collect();
+            counts.push(row);
+        }
+        counts
+    }
+
+    /// The length in characters of the shortest accepted string, or `None` if there are none
+    pub fn min_len(&self) -> Option<usize> {
+        self.length_bounds(char_lengths).map(|(min, _)| min)
+    }
+
+    /// The length in characters of the longest accepted string, or `None` if there are arbitrarily long ones (or
+    /// none at all)
+    pub fn max_len(&self) -> Option<usize> {
+        self.length_bounds(char_lengths).and_then(|(_, max)| max)
+    }
+
+    /// Like [`DFA::min_len`], but in UTF-8 bytes
+    pub fn min_byte_len(&self) -> Option<usize> {
+        self.length_bounds(byte_lengths).map(|(min, _)| min)
+    }
+
+    /// Like [`DFA::max_len`], but in UTF-8 bytes
+    pub fn max_byte_len(&self) -> Option<usize> {
+        self.length_bounds(byte_lengths).and_then(|(_, max)| max)
+    }
+
+    /// Whether the DFA accepts a finite number of strings
+    pub fn is_finite(&self) -> bool {
+        self.length_bounds(char_lengths).is_none_or(|(_, max)| max.is_some())
+    }
+
+    /// The number of accepted strings that are `len` characters long, saturating at `u128::MAX`
+    pub fn count_of_length(&self, len: usize) -> u128 {
+        self.path_counts(len)[len][0]
+    }
+
+    /// The (min, max) total weight of paths to accepting states, where `weight` gives the (min, max) weight of
+    /// each transition, or `None` to skip it. `None` if nothing is accepted, and a max of `None` if it is unbounded.
+    fn length_bounds(&self, weight: impl Fn(&TransitionType) -> Option<(usize, usize)>) -> Option<(usize, Option<usize>)> {
+        let useful = self.useful_states();
+        if !useful[0] {
+            return None;
+        }
+        let edges = |s: usize| {
+            self.transitions[s].1.iter().filter(|(_, ns)| useful[*ns]).filter_map(|(t, ns)| Some((weight(t)?, *ns)))
+        };
+
+        // Dijkstra's algorithm
+        let mut min = vec![usize::MAX; self.transitions.len()];
+        let mut heap = BinaryHeap::from([Reverse((0, 0))]);
+        let mut shortest = None;
+        while let Some(Reverse((d, s))) = heap.pop() {
+            if d >= min[s] {
+                continue;
+            }
+            min[s] = d;
+            if self.transitions[s].0 {
+                shortest = Some(shortest.map_or(d, |x: usize| x.min(d)));
+            }
+            for ((w, _), ns) in edges(s) {
+                heap.push(Reverse((d + w, ns)));
+            }
+        }
+
+        // Longest paths in topological order, which only exists if there are no cycles
+        let mut in_degree = vec![0; self.transitions.len()];
+        for s in (0..self.transitions.len()).filter(|s| useful[*s]) {
+            for (_, ns) in edges(s) {
+                in_degree[ns] += 1;
+            }
+        }
+        let mut order = (0..self.transitions.len()).filter(|s| useful[*s] && in_degree[*s] == 0).collect_vec();
+        let mut i = 0;
+        while i < order.len() {
+            for (_, ns) in edges(order[i]) {
+                in_degree[ns] -= 1;
+                if in_degree[ns] == 0 {
+                    order.push(ns);
+                }
+            }
+            i += 1;
+        }
+        if order.len() < useful.iter().filter(|u| **u).count() {
+            return Some((shortest?, None));
+        }
+
+        let mut longest = vec![0; self.transitions.len()];
+        for s in order.into_iter().rev() {
+            let via = edges(s).map(|((_, w), ns)| w + longest[ns]).max();
+            longest[s] = via.unwrap_or(0);
+        }
+        Some((shortest?, Some(longest[0])))
+    }
+
+    /// States that are reachable from the start and can reach an accepting state
+    pub(crate) fn useful_states(&self) -> Vec<bool> {
+        let mut reachable = vec![false; self.transitions.len()];
+        let mut stack = vec![0];
+        reachable[0] = true;
+        while let Some(s) = stack.pop() {
+            for (_, ns) in &self.transitions[s].1 {
+                if !reachable[*ns] {
+                    reachable[*ns] = true;
+                    stack.push(*ns);
+                }
+            }
+        }
+
+        reachable.into_iter().zip(self.live_states()).map(|(r, u)| r && u).collect()
+    }
+
+    /// Whether the DFA accepts no strings at all
+    pub fn is_empty(&self) -> bool {
+        self.shortest_accepted().is_none()
+    }
+
+    /// The shortest string the DFA rejects, preferring printable characters, or `None` if it accepts everything
+    pub fn shortest_rejected(&self) -> Option<String> {
+        self.complement().shortest_accepted()
+    }
+
+    /// The shortest string the DFA accepts, preferring printable characters, or `None` if it accepts nothing
+    pub fn shortest_accepted(&self) -> Option<String> {
+        // Breadth first search recording how each state was first reached
+        let mut previous: Vec<Option<(usize, char)>> = vec![None; self.transitions.len()];
+        let mut seen = vec![false; self.transitions.len()];
+        let mut queue = VecDeque::from([0]);
+        seen[0] = true;
+
+        while let Some(state) = queue.pop_front() {
+            if self.transitions[state].0 {
+                let mut s = Vec::new();
+                let mut current = state;
+                while let Some((prev, c)) = previous[current] {
+                    s.push(c);
+                    current = prev;
+                }
+                return Some(s.into_iter().rev().collect());
+            }
+
+            for (t, ns) in &self.transitions[state].1 {
+                if !seen[*ns] {
+                    seen[*ns] = true;
+                    previous[*ns] = Some((state, example_char(t.codepoints())));
+                    queue.push_back(*ns);
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Number of characters in the (inclusive) codepoint range `a..=b`, excluding surrogates
+pub(crate) fn char_count(a: u32, b: u32) -> u128 {
+    let surrogates = 0xD800u32..=0xDFFF;
+    let overlap = b.min(*surrogates.end()).saturating_add(1).saturating_sub(a.max(*surrogates.start()));
+    (b - a + 1 - overlap) as u128
+}
+
+/// A length of 1 for each character, or `None` if `t` matches no characters
+fn char_lengths(t: &TransitionType) -> Option<(usize, usize)> {
+    (!t.codepoints().is_empty()).then_some((1, 1))
+}
+
+/// (min, max) UTF-8 length of the characters matched by `t`, or `None` if it matches none
+fn byte_lengths(t: &TransitionType) -> Option<(usize, usize)> {
+    let len = |c: u32| char::from_u32(c).map_or(3, char::len_utf8);
+    let ranges = t.codepoints();
+    Some((len(ranges.first()?.0), len(ranges.last()?.1)))
+}
+
+/// A character from `ranges`, avoiding control characters where possible
+fn example_char(ranges: Vec<(u32, u32)>) -> char {
+    let printable = ranges.iter().find_map(|(a, b)| {
+        (*a.max(&0x20)..=*b).find_map(char::from_u32).filter(|c| !c.is_control())
+    });
+    printable.or_else(|| ranges.iter().find_map(|(a, b)| (*a..=*b).find_map(char::from_u32))).unwrap()
+}
+
+/// Whether every string `a` accepts is accepted by `b`, returning the shortest one that isn't otherwise
+pub fn is_subset(a: &DFA, b: &DFA) -> Result<(), String> {
+    match a.difference(b).shortest_accepted() {
+        Some(counterexample) => Err(counterexample),
+        None => Ok(()),
+    }
+}
+
+/// Whether `a` and `b` accept exactly the same strings, returning the shortest one accepted by only one of them
+/// otherwise
+pub fn is_equivalent(a: &DFA, b: &DFA) -> Result<(), String> {
+    match (is_subset(a, b), is_subset(b, a)) {
+        (Err(x), Err(y)) => Err(if y.chars().count() < x.chars().count() { y } else { x }),
+        (Err(x), _) | (_, Err(x)) => Err(x),
+        _ => Ok(()),
+    }
+}
@@ -1,12 +1,16 @@
 use crate::automata::TransitionType::{Any, ExcludeRange, Range, Single};
-use crate::regex::{ChainedMatchable, Matchable, Repetition};
+use crate::error::CompileError;
+use crate::glushkov::glushkov_nfa;
+use crate::options::{CompileOptions, Construction};
+use crate::regex::{ChainedMatchable, InvertibleMatchable, Matchable, Repetition};
 use const_regex_util::{char_to_utf8, next_char, utf8_to_char};
 use itertools::Itertools;
-use proc_macro2::TokenStream;
-use quote::{quote, ToTokens, TokenStreamExt};
-use std::collections::{HashMap, HashSet, VecDeque};
+use proc_macro2::{Ident, TokenStream};
+use quote::{format_ident, quote, ToTokens, TokenStreamExt};
+use std::collections::HashMap;
 use std::fmt::{Debug, Formatter};
 use std::hash::Hash;
+use std::ops::RangeInclusive;
 
 #[repr(u8)]
 #[derive(Copy, Clone, Hash, Eq, PartialEq)]
@@ -43,82 +47,330 @@ impl Debug for TransitionType {
     }
 }
 
-#[derive(Default, Debug)]
-struct TransitionHolder {
-    inner: HashMap<TransitionType, HashSet<usize>>
-}
+impl TransitionType {
+    /// Whether the UTF-8 encoded character `c` (as returned by [`next_char`]) matches this transition
+    pub const fn matches(&self, c: u32) -> bool {
+        match *self {
+            Single(a) => a == c,
+            Range(a, b) => a <= c && c <= b,
+            ExcludeRange(a, b) => c < a || b < c,
+            Any => true,
+        }
+    }
 
-impl TransitionHolder {
-    // fn add_transition(&mut self, tt: TransitionType, destination: usize) {
-    //     if let Some(s) = self.inner.get_mut(&tt) {
-    //         s.insert(destination);
-    //     }
-    //     else {
-    //         self.inner.insert(tt, HashSet::from([destination]));
-    //     }
-    // }
+    /// The character ranges matched by this transition
+    pub fn codepoint_ranges(&self) -> Vec<RangeInclusive<char>> {
+        self.codepoints().into_iter().filter_map(|(a, b)| match codepoint_transition(a, b)? {
+            Single(c) => Some(utf8_to_char(c)..=utf8_to_char(c)),
+            Range(a, b) => Some(utf8_to_char(a)..=utf8_to_char(b)),
+            _ => unreachable!()
+        }).collect()
+    }
 
-    fn add_transitions(&mut self, tt: TransitionType, destinations: HashSet<usize>) {
-        if let Some(ss) = self.inner.get_mut(&tt) {
-            for s in destinations {
-                ss.insert(s);
+    /// The (inclusive) codepoint ranges matched by this transition
+    pub(crate) fn codepoints(&self) -> Vec<(u32, u32)> {
+        let cp = |c: u32| utf8_to_char(c) as u32;
+        match *self {
+            Single(c) => vec![(cp(c), cp(c))],
+            Range(a, b) => vec![(cp(a), cp(b))],
+            ExcludeRange(a, b) => {
+                let (a, b) = (cp(a), cp(b));
+                let mut ranges = Vec::new();
+                if a > 0 {
+                    ranges.push((0, a - 1));
+                }
+                if b < char::MAX as u32 {
+                    ranges.push((b + 1, char::MAX as u32));
+                }
+                ranges
             }
-        }
-        else {
-            self.inner.insert(tt, destinations);
+            Any => vec![(0, char::MAX as u32)],
         }
     }
+}
 
-    fn collapse_transitions(self) -> TransitionHolder  {
-        let mut ts = self.inner;
+#[derive(Default, Clone)]
+pub struct DFA {
+    pub transitions: Vec<(bool, Vec<(TransitionType, usize)>)>
+}
 
-        let mut changed = true;
-        'outer: loop {
+/// Emits an expression evaluating to a value with a `const fn test(&self, input: &str) -> bool` method matching
+/// the DFA, `MIN_BYTE_LEN` / `MAX_BYTE_LEN` lengths and `shortest_match` / `shortest_non_match` examples. This is
+/// what `regex!` expands to - build scripts can write it to a file and `include!` it.
+impl ToTokens for DFA {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        tokens.append_all(self.matcher(&self.reverse(), &self.then_anything().reverse()));
+    }
+}
 
-            let mut keys = ts.keys().cloned().collect_vec();
-            for i in 0..keys.len() {
-                let k1 = &keys[i];
+impl DFA {
+    /// The [`ToTokens`] output, failing if a reversed DFA used by `ends_with_match` or `rfind` would have more
+    /// than `options.max_dfa_states` states
+    pub fn try_to_tokens(&self, options: &CompileOptions) -> Result<TokenStream, CompileError> {
+        let suffixes = self.try_reverse(options.max_dfa_states)?;
+        let starts = self.then_anything().try_reverse(options.max_dfa_states)?;
+        Ok(self.matcher(&suffixes, &starts))
+    }
 
-                match &k1 {
-                    Single(_) => { continue; }
-                    Range(_, _) => {}
-                    ExcludeRange(_, _) => {}
-                    Any => {}
-                };
+    /// The matcher for the DFA, given the reverse of the DFA and the reverse of the DFA followed by anything
+    fn matcher(&self, suffixes: &DFA, starts: &DFA) -> TokenStream {
+        let example = |s: Option<String>| match s {
+            Some(s) => quote! { Some(#s) },
+            None => quote! { None },
+        };
+        let shortest_match = example(self.shortest_accepted());
+        let shortest_non_match = example(self.shortest_rejected());
+        // An empty language gets an impossible range
+        let min_len = self.min_byte_len().unwrap_or(usize::MAX);
+        let max_len = if self.is_empty() { 0 } else { self.max_byte_len().unwrap_or(usize::MAX) };
+
+        let transitions = self.transition_consts("");
+        let test = walk(quote! { TRANSITIONS[state].0 }, quote! { false }, quote! {});
+        let longest_prefix = walk(
+            quote! { longest },
+            quote! { longest },
+            quote! { if TRANSITIONS[state].0 { longest = Some(string_pos); } }
+        );
+        let shortest_prefix = walk(
+            quote! { None },
+            quote! { None },
+            quote! { if TRANSITIONS[state].0 { return Some(string_pos); } }
+        );
+
+        let (suffix_table, start_table) = (format_ident!("SUFFIX_TRANSITIONS"), format_ident!("START_TRANSITIONS"));
+        let ends_with_match = walk_back(
+            &suffix_table,
+            quote! { false },
+            quote! { false },
+            quote! { if #suffix_table[state].0 { return true; } }
+        );
+        let suffix_consts = suffixes.transition_consts("SUFFIX_");
+        let ends_with_match = quote! {
+            if input.len() < Self::MIN_BYTE_LEN {
+                return false;
+            }
+            if #suffix_table[0].0 {
+                return true;
+            }
+            #ends_with_match
+        };
+        let rfind = walk_back(
+            &start_table,
+            quote! { None },
+            quote! { None },
+            quote! { if #start_table[state].0 { return Some(string_pos); } }
+        );
+        let start_consts = starts.transition_consts("START_");
+        let rfind = quote! {
+            if input.len() < Self::MIN_BYTE_LEN {
+                return None;
+            }
+            if #start_table[0].0 {
+                return Some(input.len());
+            }
+            #rfind
+        };
 
-                for j in 0..keys.len() {
-                    if j == i { continue; }
+        let live = self.live_states();
+        let states = live.len();
+        let allowed = (0..states).map(|state| {
+            let ranges = self.allowed(state, &live).into_iter().map(|r| {
+                let (a, b) = (r.start(), r.end());
+                quote! { (#a, #b) }
+            });
+            quote! { &[#(#ranges),*] }
+        });
+        let is_viable_prefix = walk(quote! { LIVE[state] }, quote! { false }, quote! { if !LIVE[state] { return false; } });
+        let state = walk(quote! { Some(state) }, quote! { None }, quote! {});
+
+        quote! {
+            {
+                #transitions
+                #suffix_consts
+                #start_consts
+                /// Whether each state can reach an accepting state
+                const LIVE: [bool; #states] = [#(#live),*];
+                /// The characters leading from each state to a live state
+                const ALLOWED: [&[(char, char)]; #states] = [#(#allowed),*];
+
+                struct Regex;
+
+                impl Regex {
+                    /// The length in bytes of the shortest string this regex matches
+                    pub const MIN_BYTE_LEN: usize = #min_len;
+                    /// The length in bytes of the longest string this regex matches, or `usize::MAX` if unbounded
+                    pub const MAX_BYTE_LEN: usize = #max_len;
+
+                    pub const fn min_byte_len(&self) -> usize {
+                        Self::MIN_BYTE_LEN
+                    }
 
-                    let k2 = &keys[j];
+                    pub const fn max_byte_len(&self) -> usize {
+                        Self::MAX_BYTE_LEN
+                    }
 
-                    match &k1 {
-                        Single(_) => { unreachable!(); }
-                        Range(_, _) => {
+                    /// The shortest string this regex matches
+                    pub const fn shortest_match(&self) -> Option<&'static str> {
+                        #shortest_match
+                    }
+
+                    /// The shortest string this regex doesn't match
+                    pub const fn shortest_non_match(&self) -> Option<&'static str> {
+                        #shortest_non_match
+                    }
 
+                    pub const fn test(&self, input: &str) -> bool {
+                        if input.len() < Self::MIN_BYTE_LEN || input.len() > Self::MAX_BYTE_LEN {
+                            return false;
                         }
-                        ExcludeRange(_, _) => {
 
+                        #test
+                    }
+
+                    /// The length in bytes of the longest prefix of `input` this regex matches
+                    pub const fn longest_prefix(&self, input: &str) -> Option<usize> {
+                        let mut longest = if TRANSITIONS[0].0 { Some(0) } else { None };
+                        #longest_prefix
+                    }
+
+                    /// The length in bytes of the shortest prefix of `input` this regex matches
+                    pub const fn shortest_prefix(&self, input: &str) -> Option<usize> {
+                        if TRANSITIONS[0].0 {
+                            return Some(0);
                         }
-                        Any => {
+                        #shortest_prefix
+                    }
+
+                    /// Whether some suffix of `input` matches this regex
+                    pub const fn ends_with_match(&self, input: &str) -> bool {
+                        #ends_with_match
+                    }
 
+                    /// The byte index of the start of the last match of this regex in `input`
+                    pub const fn rfind(&self, input: &str) -> Option<usize> {
+                        #rfind
+                    }
+
+                    /// Whether `input` can be extended to a string this regex matches
+                    pub const fn is_viable_prefix(&self, input: &str) -> bool {
+                        #is_viable_prefix
+                    }
+
+                    /// The characters that can follow `input` in a string this regex matches, as sorted, disjoint
+                    /// ranges
+                    pub fn next_allowed(&self, input: &str) -> Vec<std::ops::RangeInclusive<char>> {
+                        const fn state(input: &str) -> Option<usize> {
+                            #state
+                        }
+
+                        match state(input) {
+                            Some(state) => ALLOWED[state].iter().map(|(a, b)| *a..=*b).collect(),
+                            None => Vec::new(),
                         }
                     }
                 }
+
+                Regex {}
+            }
+        }
+    }
+
+    /// Consts for the transitions of each state, ending with `{prefix}TRANSITIONS`, an array of every state's
+    /// success flag and transitions
+    pub(crate) fn transition_consts(&self, prefix: &str) -> TokenStream {
+        let mut transitions = TokenStream::new();
+
+        for (i, (s, ts)) in self.transitions.iter().enumerate() {
+            let success_ident = format_ident!("{prefix}T{i}_SUCCESS");
+            let ident = format_ident!("{prefix}T{i}");
+            let t_len = ts.len();
+
+            let mut ts_tokens = TokenStream::new();
+            for (tt, dst) in ts {
+                ts_tokens.append_all(quote! {
+                    (#tt, #dst),
+                });
             }
 
-            break;
+            transitions.append_all(quote! {
+                const #success_ident: bool = #s;
+                const #ident: [(const_regex_regex_transformer::automata::TransitionType, usize); #t_len] = [#ts_tokens];
+            });
         }
 
-        TransitionHolder {
-            inner: ts
+        let mut full_tokens = TokenStream::new();
+        for i in 0..self.transitions.len() {
+            let success_ident = format_ident!("{prefix}T{i}_SUCCESS");
+            let ident = format_ident!("{prefix}T{i}");
+            full_tokens.append_all(quote! {
+                (#success_ident, &#ident),
+            });
         }
+
+        let t_len = self.transitions.len();
+        let table = format_ident!("{prefix}TRANSITIONS");
+        transitions.append_all(quote! {
+            const #table: [(bool, &[(const_regex_regex_transformer::automata::TransitionType, usize)]); #t_len] = [#full_tokens];
+        });
+
+        transitions
     }
 }
 
+/// Runs the DFA in `TRANSITIONS` over `input`, then returns `on_end` (with the final state in `state`) from the
+/// enclosing function, or `on_dead` if it falls off the DFA. `on_step` runs after each transition, with the new
+/// state in `state` and the number of bytes read in `string_pos`.
+pub(crate) fn walk(on_end: TokenStream, on_dead: TokenStream, on_step: TokenStream) -> TokenStream {
+    quote! {
+        let mut state = 0;
+        let mut string_pos = 0;
+        'outer: loop {
+            if state >= TRANSITIONS.len() { panic!("Invalid DFA state!"); }
+            let ts = TRANSITIONS[state].1;
+            if string_pos == input.as_bytes().len() { return #on_end; }
+            let (c, d) = const_regex_util::next_char(input, string_pos);
+            string_pos = d;
+            let mut i = 0;
+            let len = ts.len();
+            while i < len {
+                let (t, ns) = &ts[i];
+                if t.matches(c) {
+                    state = *ns;
+                    #on_step
+                    continue 'outer;
+                }
+                i += 1;
+            }
+            return #on_dead;
+        }
+    }
+}
 
-#[derive(Default)]
-pub struct DFA {
-    pub transitions: Vec<(bool, Vec<(TransitionType, usize)>)>
+/// Like [`walk`], but runs the DFA in `table` backwards from the end of `input`, with `string_pos` the start of
+/// the characters read so far
+pub(crate) fn walk_back(table: &Ident, on_end: TokenStream, on_dead: TokenStream, on_step: TokenStream) -> TokenStream {
+    quote! {
+        let mut state = 0;
+        let mut string_pos = input.as_bytes().len();
+        'outer: loop {
+            if state >= #table.len() { panic!("Invalid DFA state!"); }
+            let ts = #table[state].1;
+            let Some((c, d)) = const_regex_util::prev_char(input, string_pos) else { return #on_end; };
+            string_pos = d;
+            let mut i = 0;
+            let len = ts.len();
+            while i < len {
+                let (t, ns) = &ts[i];
+                if t.matches(c) {
+                    state = *ns;
+                    #on_step
+                    continue 'outer;
+                }
+                i += 1;
+            }
+            return #on_dead;
+        }
+    }
 }
 
 impl Debug for DFA {
@@ -134,9 +386,327 @@ impl Debug for DFA {
     }
 }
 
+impl DFA {
+    /// Whether the DFA accepts `s`
+    pub fn test(&self, s: &str) -> bool {
+        self.run(s).is_some_and(|state| self.transitions[state].0)
+    }
+
+    /// The length in bytes of the longest prefix of `s` the DFA accepts
+    pub fn longest_prefix(&self, s: &str) -> Option<usize> {
+        self.prefixes(s).last()
+    }
+
+    /// The length in bytes of the shortest prefix of `s` the DFA accepts
+    pub fn shortest_prefix(&self, s: &str) -> Option<usize> {
+        self.prefixes(s).next()
+    }
+
+    /// The lengths of the prefixes of `s` the DFA accepts, in increasing order
+    fn prefixes<'a>(&'a self, s: &'a str) -> impl Iterator<Item=usize> + 'a {
+        let ends = s.char_indices().map(|(i, c)| (i + c.len_utf8(), char_to_utf8(c)));
+        let states = ends.scan(0, |state, (end, c)| {
+            *state = self.transitions[*state].1.iter().find(|(t, _)| t.matches(c))?.1;
+            Some((end, *state))
+        });
+        std::iter::once((0, 0)).chain(states).filter(|(_, state)| self.transitions[*state].0).map(|(end, _)| end)
+    }
+
+    /// Whether `s` can be extended to a string the DFA accepts
+    pub fn is_viable_prefix(&self, s: &str) -> bool {
+        self.run(s).is_some_and(|state| self.live_states()[state])
+    }
+
+    /// The characters that can follow `s` in a string the DFA accepts, as sorted, disjoint ranges
+    pub fn next_allowed(&self, s: &str) -> Vec<RangeInclusive<char>> {
+        self.run(s).map(|state| self.allowed(state, &self.live_states())).unwrap_or_default()
+    }
+
+    /// The characters leading from `state` to one of the `live` states, as sorted, disjoint ranges
+    pub(crate) fn allowed(&self, state: usize, live: &[bool]) -> Vec<RangeInclusive<char>> {
+        let ranges = self.transitions[state].1.iter()
+            .filter(|(_, ns)| live[*ns])
+            .flat_map(|(t, _)| t.codepoints())
+            .sorted_unstable()
+            .map(|(a, b)| (a, b, 0));
+        merge_adjacent(ranges).into_iter()
+            .map(|(a, b, _)| char::from_u32(a).unwrap_or('\u{E000}')..=char::from_u32(b).unwrap_or('\u{D7FF}'))
+            .collect()
+    }
+
+    /// The state reached after reading `s`, or `None` if it falls off the DFA first
+    pub(crate) fn run(&self, s: &str) -> Option<usize> {
+        let mut state = 0;
+        let mut pos = 0;
+        while pos < s.len() {
+            let (c, new_pos) = next_char(s, pos);
+            pos = new_pos;
+            state = self.transitions[state].1.iter().find(|(t, _)| t.matches(c))?.1;
+        }
+        Some(state)
+    }
+
+    /// Strings accepted by either DFA
+    pub fn union(&self, other: &DFA) -> DFA {
+        self.product(other, |a, b| a || b)
+    }
+
+    /// Strings accepted by both DFAs
+    pub fn intersection(&self, other: &DFA) -> DFA {
+        self.product(other, |a, b| a && b)
+    }
+
+    /// Strings accepted by `self` but not `other`
+    pub fn difference(&self, other: &DFA) -> DFA {
+        self.product(other, |a, b| a && !b)
+    }
+
+    /// Every string not accepted by the DFA
+    pub fn complement(&self) -> DFA {
+        let table = self.codepoint_table();
+        // `None` is the (now accepting) dead state
+        explore(
+            Some(0),
+            |s| !s.is_some_and(|s| self.transitions[s].0),
+            |s, boundaries| if let Some(s) = s { table.boundaries(*s, boundaries) },
+            |s, c| Some(s.and_then(|s| table.step(s, c))),
+            u64::MAX,
+        ).unwrap().trim()
+    }
+
+    /// Strings with a prefix accepted by the DFA
+    pub(crate) fn then_anything(&self) -> DFA {
+        let transitions = self.transitions.iter().enumerate().map(|(i, (success, ts))| {
+            if *success { (true, vec![(Any, i)]) } else { (false, ts.clone()) }
+        }).collect();
+        DFA { transitions }.trim()
+    }
+
+    /// Strings whose reverse is accepted by the DFA
+    pub fn reverse(&self) -> DFA {
+        self.try_reverse(u64::MAX).unwrap()
+    }
+
+    /// [`DFA::reverse`], failing if the result would have more than `max_states` states
+    pub fn try_reverse(&self, max_states: u64) -> Result<DFA, CompileError> {
+        // Incoming transitions of each state as (first codepoint, last codepoint, source)
+        let mut incoming: Vec<Vec<(u32, u32, usize)>> = vec![Vec::new(); self.transitions.len()];
+        for (src, ranges) in self.codepoint_table().0.into_iter().enumerate() {
+            for (a, b, dst) in ranges {
+                incoming[dst].push((a, b, src));
+            }
+        }
+
+        let start = (0..self.transitions.len()).filter(|s| self.transitions[*s].0).collect_vec();
+        explore(
+            start,
+            |set| set.binary_search(&0).is_ok(),
+            |set, boundaries| {
+                boundaries.extend(set.iter().flat_map(|s| &incoming[*s]).flat_map(|(a, b, _)| [*a, b + 1]));
+            },
+            |set, c| {
+                let mut next = set.iter()
+                    .flat_map(|s| &incoming[*s])
+                    .filter(|(a, b, _)| *a <= c && c <= *b)
+                    .map(|(_, _, src)| *src)
+                    .collect_vec();
+                next.sort_unstable();
+                next.dedup();
+                (!next.is_empty()).then_some(next)
+            },
+            max_states,
+        ).map(DFA::trim)
+    }
+
+    /// The equivalent DFA with the fewest states
+    pub fn minimize(&self) -> DFA {
+        let dfa = self.clone().trim();
+        let table = dfa.codepoint_table();
+
+        // Moore's algorithm: split states into classes until every state in a class has the same transitions
+        // into classes
+        let mut classes = dfa.transitions.iter().map(|(s, _)| *s as usize).collect_vec();
+        let mut count = 0;
+        loop {
+            // (class, transitions into classes) -> new class
+            let mut ids = HashMap::new();
+            let next: Vec<usize> = (0..dfa.transitions.len()).map(|s| {
+                let signature = (classes[s], merge_adjacent(table.0[s].iter().map(|(a, b, ns)| (*a, *b, classes[*ns]))));
+                let id = ids.len();
+                *ids.entry(signature).or_insert(id)
+            }).collect_vec();
+
+            classes = next;
+            if ids.len() == count {
+                break;
+            }
+            count = ids.len();
+        }
+
+        let mut transitions = vec![None; count];
+        for (s, class) in classes.iter().enumerate() {
+            transitions[*class].get_or_insert_with(|| {
+                let ranges = merge_adjacent(table.0[s].iter().map(|(a, b, ns)| (*a, *b, classes[*ns])));
+                let ts = ranges.into_iter().filter_map(|(a, b, ns)| Some((codepoint_transition(a, b)?, ns))).collect();
+                (dfa.transitions[s].0, ts)
+            });
+        }
+        DFA { transitions: transitions.into_iter().map(Option::unwrap).collect() }
+    }
+
+    /// Runs both DFAs in lockstep, accepting when `accept` holds for whether each of them accepts.
+    /// `accept(false, false)` must be `false`.
+    fn product(&self, other: &DFA, accept: impl Fn(bool, bool) -> bool) -> DFA {
+        let (left, right) = (self.codepoint_table(), other.codepoint_table());
+        let is_success = |s: Option<usize>, dfa: &DFA| s.is_some_and(|s| dfa.transitions[s].0);
+        explore(
+            (Some(0), Some(0)),
+            |(a, b)| accept(is_success(*a, self), is_success(*b, other)),
+            |(a, b), boundaries| {
+                if let Some(a) = a { left.boundaries(*a, boundaries) }
+                if let Some(b) = b { right.boundaries(*b, boundaries) }
+            },
+            |(a, b), c| match (a.and_then(|a| left.step(a, c)), b.and_then(|b| right.step(b, c))) {
+                (None, None) => None,
+                next => Some(next),
+            },
+            u64::MAX,
+        ).unwrap().trim()
+    }
+
+    pub(crate) fn codepoint_table(&self) -> CodepointTable {
+        CodepointTable(self.transitions.iter().map(|(_, ts)| {
+            ts.iter()
+                .flat_map(|(t, ns)| t.codepoints().into_iter().map(move |(a, b)| (a, b, *ns)))
+                .sorted_unstable()
+                .collect()
+        }).collect())
+    }
+
+    /// Whether an accepting state can be reached from each state
+    pub(crate) fn live_states(&self) -> Vec<bool> {
+        let mut live = self.transitions.iter().map(|(s, _)| *s).collect_vec();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (i, (_, ts)) in self.transitions.iter().enumerate() {
+                if !live[i] && ts.iter().any(|(_, ns)| live[*ns]) {
+                    live[i] = true;
+                    changed = true;
+                }
+            }
+        }
+        live
+    }
+
+    /// Removes every state (other than the start) from which no accepting state can be reached
+    fn trim(self) -> DFA {
+        let mut live = self.live_states();
+        live[0] = true;
+
+        let mut ids = vec![usize::MAX; live.len()];
+        let mut next = 0;
+        for (i, l) in live.iter().enumerate() {
+            if *l {
+                ids[i] = next;
+                next += 1;
+            }
+        }
+
+        let transitions = self.transitions.into_iter().enumerate()
+            .filter(|(i, _)| live[*i])
+            .map(|(_, (s, ts))| (s, ts.into_iter().filter(|(_, ns)| live[*ns]).map(|(t, ns)| (t, ids[ns])).collect()))
+            .collect();
+        DFA { transitions }
+    }
+}
+
+/// The transitions of each DFA state as (first codepoint, last codepoint, destination), sorted by codepoint
+#[derive(Debug, Clone)]
+pub(crate) struct CodepointTable(pub(crate) Vec<Vec<(u32, u32, usize)>>);
+
+impl CodepointTable {
+    fn boundaries(&self, state: usize, boundaries: &mut Vec<u32>) {
+        boundaries.extend(self.0[state].iter().flat_map(|(a, b, _)| [*a, b + 1]));
+    }
+
+    pub(crate) fn step(&self, state: usize, c: u32) -> Option<usize> {
+        self.0[state].iter().find(|(a, b, _)| *a <= c && c <= *b).map(|(_, _, ns)| *ns)
+    }
+}
+
+/// Joins sorted (first codepoint, last codepoint, destination) ranges that are next to each other (ignoring
+/// surrogates) and have the same destination
+pub(crate) fn merge_adjacent(ranges: impl Iterator<Item=(u32, u32, usize)>) -> Vec<(u32, u32, usize)> {
+    let mut merged: Vec<(u32, u32, usize)> = Vec::new();
+    for (a, b, ns) in ranges {
+        match merged.last_mut() {
+            Some((_, end, last)) if *last == ns && (*end + 1 == a || (*end, a) == (0xD7FF, 0xE000)) => *end = b,
+            _ => merged.push((a, b, ns)),
+        }
+    }
+    merged
+}
+
+/// Builds a DFA from the states reachable from `start`. `boundaries` adds the codepoints at which the transitions
+/// out of a state can change, and `step` gives the state reached on a codepoint (`None` if there isn't one).
+pub(crate) fn explore<K: Clone + Eq + Hash>(
+    start: K,
+    is_success: impl Fn(&K) -> bool,
+    boundaries: impl Fn(&K, &mut Vec<u32>),
+    step: impl Fn(&K, u32) -> Option<K>,
+    max_states: u64,
+) -> Result<DFA, CompileError> {
+    let mut ids: HashMap<K, usize> = HashMap::new();
+    ids.insert(start.clone(), 0);
+    let mut states = vec![start];
+
+    let mut dfa = DFA::default();
+    while dfa.transitions.len() < states.len() {
+        let state = states[dfa.transitions.len()].clone();
+
+        let mut points = vec![0, char::MAX as u32 + 1];
+        boundaries(&state, &mut points);
+        points.sort_unstable();
+        points.dedup();
+
+        // Every character between two boundaries leads to the same state
+        let mut ranges: Vec<(u32, u32, usize)> = Vec::new();
+        for w in points.windows(2) {
+            let (a, b) = (w[0], w[1] - 1);
+            let Some(representative) = (a..=b).find(|c| char::from_u32(*c).is_some()) else { continue; };
+            let Some(next) = step(&state, representative) else { continue; };
+
+            let id = match ids.get(&next) {
+                Some(id) => *id,
+                None => {
+                    if states.len() as u64 >= max_states {
+                        return Err(CompileError::TooManyDfaStates { limit: max_states });
+                    }
+                    ids.insert(next.clone(), states.len());
+                    states.push(next);
+                    states.len() - 1
+                }
+            };
+
+            match ranges.last_mut() {
+                Some((_, end, last)) if *last == id && *end + 1 == a => *end = b,
+                _ => ranges.push((a, b, id)),
+            }
+        }
+
+        let transitions = ranges.into_iter()
+            .filter_map(|(a, b, id)| Some((codepoint_transition(a, b)?, id)))
+            .collect();
+        dfa.transitions.push((is_success(&state), transitions));
+    }
+
+    Ok(dfa)
+}
+
 #[derive(Default, Clone)]
-struct NFAState {
-    transitions: Vec<(Option<TransitionType>, usize)>
+pub(crate) struct NFAState {
+    pub(crate) transitions: Vec<(Option<TransitionType>, usize)>
 }
 
 impl NFAState {
@@ -161,8 +731,15 @@ impl Debug for NFAState {
     }
 }
 
+/// An NFA starting at state `0`. Inputs are accepted if state `states.len()` can be reached.
 pub struct NFA {
-    states: Vec<NFAState>
+    pub(crate) states: Vec<NFAState>
+}
+
+impl NFA {
+    pub fn state_count(&self) -> usize {
+        self.states.len()
+    }
 }
 
 impl Debug for NFA {
@@ -177,243 +754,428 @@ impl Debug for NFA {
 }
 
 pub fn to_nfa(regex: ChainedMatchable) -> NFA {
-    let mut states = Vec::new();
-    let success_states = recursive_nfa(&regex, &mut states);
-
-    // Make all success states succeed
-    for s in success_states {
-        let next = states.len();
-        states[s].add_transition(None, next);
+    match try_to_nfa(regex, &CompileOptions::default()) {
+        Ok(nfa) => nfa,
+        Err(e) => panic!("{e}"),
     }
-    NFA { states }
 }
 
-fn recursive_nfa(section: &ChainedMatchable, transitions: &mut Vec<NFAState>) -> Vec<usize> {
-    // Assume entry at state = transitions.len()
-    // Make exit as transitions.len() after completion
-    let (m, r, n) = (section.matchables(), section.repetition(), section.next());
-
-    let start_state_pos = transitions.len();
-    let start_state = NFAState::default();
-    transitions.push(start_state);
-
-    // Loop (repeat states)
-    let (repeats, accepting, last_looped) = match r {
-        Repetition::One => (1, 1..2, false),
-        Repetition::Any => (1, 0..2, true),
-        Repetition::AtLeast(x) => (*x, *x..*x+1, true),
-        Repetition::LessThanEq(x) => (*x, 0..*x+1, false),
-        Repetition::Range(x, y) => (*y, *x..*y+1, false),
-    };
-
-    let mut all_success_states = vec![start_state_pos];
-    let mut prev_success_state = start_state_pos;
-
-    for _ in 0..repeats {
-        // Build all matchables
-        let mut matchables_success_states = Vec::new();
-        for m in m.matchable().matchables() {
-            let t_len = transitions.len();
-            transitions[prev_success_state].add_transition(None, t_len); // Transition to matchable
-            let ss = matchable_nfa(m, transitions);
-            matchables_success_states.extend_from_slice(&ss);
-        }
+/// Builds an NFA using the [`Construction`] selected in `options`, failing if it would be larger than `options`
+/// allow. The size is checked before any states are created so that e.g. `a{1,18446744073709551615}` fails
+/// immediately.
+///
+/// [`Construction::Brzozowski`] builds DFAs without an NFA, so it falls back to [`Construction::Thompson`] here
+/// (and in everything built on this, like [`LazyDFA`](crate::lazy::LazyDFA) and
+/// [`try_to_set`](crate::set::try_to_set)).
+pub fn try_to_nfa(regex: ChainedMatchable, options: &CompileOptions) -> Result<NFA, CompileError> {
+    let required = sequence_size(&regex).saturating_add(1);
+    if required > options.max_nfa_states {
+        return Err(CompileError::TooManyNfaStates { limit: options.max_nfa_states, required });
+    }
 
-        // Either tie all successes of matchables to success state or all failures
-        // Based on whether inverted
-        let success_state = NFAState::default();
-        let success_state_pos = transitions.len();
+    match options.construction {
+        Construction::Thompson => Ok(thompson_nfa(&regex)),
+        Construction::Glushkov => Ok(glushkov_nfa(&regex)),
+        Construction::Brzozowski => Ok(thompson_nfa(&regex)),
+    }
+}
 
-        if *m.inverted() {
-            for i in prev_success_state+1..transitions.len() {
-                if matchables_success_states.contains(&i) {
-                    continue;
-                }
-                transitions[i].add_transition(None, success_state_pos);
-            }
-        }
-        else {
-            for i in matchables_success_states {
-                transitions[i].add_transition(None, success_state_pos);
-            }
-        }
+fn thompson_nfa(regex: &ChainedMatchable) -> NFA {
+    let mut states = vec![NFAState::default()];
+    let exit = sequence_nfa(regex, &mut states, 0);
 
-        transitions.push(success_state);
-        all_success_states.push(success_state_pos);
-        prev_success_state = success_state_pos;
+    // Make the success state succeed
+    let next = states.len();
+    states[exit].add_transition(None, next);
+    NFA { states }
+}
+
+/// (min, max) number of times a repetition matches
+pub(crate) fn repetition_bounds(r: &Repetition) -> (u64, Option<u64>) {
+    match r {
+        Repetition::One => (1, Some(1)),
+        Repetition::Any => (0, None),
+        Repetition::AtLeast(x) => (*x, None),
+        Repetition::LessThanEq(x) => (0, Some(*x)),
+        Repetition::Range(x, y) => (*x, Some(*y)),
     }
+}
 
-    if last_looped {
-        transitions[*all_success_states.last().unwrap()].add_transition(None, start_state_pos);
+/// The [`Repetition`] matching between `min` and `max` times, the inverse of [`repetition_bounds`]
+pub(crate) fn bounds_repetition(min: u64, max: Option<u64>) -> Repetition {
+    match (min, max) {
+        (0, None) => Repetition::Any,
+        (min, None) => Repetition::AtLeast(min),
+        (1, Some(1)) => Repetition::One,
+        (0, Some(max)) => Repetition::LessThanEq(max),
+        (min, Some(max)) => Repetition::Range(min, max),
     }
+}
 
-    let final_success_state_pos = transitions.len();
-    transitions.push(NFAState::default());
+/// Whether `m` only matches single characters (and can therefore be built without any epsilon transitions)
+fn is_class(m: &InvertibleMatchable) -> bool {
+    m.matchable().matchables().iter().all(|m| !matches!(m, Matchable::Subexpression(_)))
+}
 
+/// The number of states [`sequence_nfa`] creates for `section`, saturating at `u64::MAX`
+pub(crate) fn sequence_size(section: &ChainedMatchable) -> u64 {
+    section.iter().fold(0u64, |total, c| total.saturating_add(item_size(c)))
+}
 
-    for i in accepting {
-        transitions[all_success_states[i as usize]].add_transition(None, final_success_state_pos);
+/// The number of states [`item_nfa`] creates for `item`
+fn item_size(item: &ChainedMatchable) -> u64 {
+    // Each match creates an exit state, plus the states of any subexpressions
+    let m = item.matchables();
+    let body = m.matchable().matchables().iter().fold(1u64, |total, m| match m {
+        Matchable::Subexpression(s) => total.saturating_add(sequence_size(s)),
+        _ => total,
+    });
+
+    let (min, max) = repetition_bounds(item.repetition());
+    match max {
+        // Plus a state to loop on, which classes loop on directly and anything else loops on through another match
+        None => body.saturating_mul(min).saturating_add(1).saturating_add(if is_class(m) { 0 } else { body }),
+        Some(max) if max == min => body.saturating_mul(max),
+        // Plus the shared exit
+        Some(max) => body.saturating_mul(max).saturating_add(1),
     }
+}
 
-    let final_ends = if let Some(next) = n {
-        let t_len = transitions.len();
-        transitions[final_success_state_pos].add_transition(None, t_len);
-        recursive_nfa(next, transitions)
-    } else {
-        vec![final_success_state_pos]
-    };
+/// Builds `section` starting at the existing state `entry`, returning its exit state
+fn sequence_nfa(section: &ChainedMatchable, states: &mut Vec<NFAState>, entry: usize) -> usize {
+    section.iter().fold(entry, |entry, item| item_nfa(item, states, entry))
+}
 
-    final_ends
+fn push_state(states: &mut Vec<NFAState>) -> usize {
+    states.push(NFAState::default());
+    states.len() - 1
 }
 
-fn matchable_nfa(matchable: &Matchable, transitions: &mut Vec<NFAState>) -> Vec<usize> {
-    let t = match matchable {
-        Matchable::Char(c) => {
-            Some(Single(char_to_utf8(*c)))
-        }
-        Matchable::Range(a, b) => {
-            Some(Range(char_to_utf8(*a), char_to_utf8(*b)))
+fn item_nfa(item: &ChainedMatchable, states: &mut Vec<NFAState>, entry: usize) -> usize {
+    let m = item.matchables();
+    let (min, max) = repetition_bounds(item.repetition());
+
+    let mut current = entry;
+    for _ in 0..min {
+        current = match_nfa(m, states, current);
+    }
+
+    match max {
+        None => {
+            // Loop on a fresh state so that the loop can't be entered from anything before it
+            let loop_state = push_state(states);
+            states[current].add_transition(None, loop_state);
+            if is_class(m) {
+                for t in class_transitions(m) {
+                    states[loop_state].add_transition(Some(t), loop_state);
+                }
+            }
+            else {
+                let exit = match_nfa(m, states, loop_state);
+                states[exit].add_transition(None, loop_state);
+            }
+            loop_state
         }
-        Matchable::Any => Some(Any),
-        Matchable::Subexpression(s) => {
-            return recursive_nfa(s, transitions);
+        Some(max) => {
+            if max == min {
+                return current;
+            }
+            // All optional repetitions share a single exit
+            let exit = push_state(states);
+            states[current].add_transition(None, exit);
+            for _ in min..max {
+                current = match_nfa(m, states, current);
+                states[current].add_transition(None, exit);
+            }
+            exit
         }
-    };
+    }
+}
 
-    let mut entry = NFAState::default();
-    entry.add_transition(t, transitions.len() + 1);
-    let success_pos = transitions.len() + 1;
-    let success = NFAState::default();
-    transitions.push(entry);
-    transitions.push(success);
+/// Builds a single (unrepeated) match of `m` starting at `entry`, returning its exit state
+fn match_nfa(m: &InvertibleMatchable, states: &mut Vec<NFAState>, entry: usize) -> usize {
+    let exit = push_state(states);
+    for t in class_transitions(m) {
+        states[entry].add_transition(Some(t), exit);
+    }
 
-    vec![success_pos]
+    for m in m.matchable().matchables() {
+        if let Matchable::Subexpression(s) = m {
+            let sub_exit = sequence_nfa(s, states, entry);
+            states[sub_exit].add_transition(None, exit);
+        }
+    }
+    exit
 }
 
-fn gather_epsilon(nfa: &NFA, states: &mut HashSet<usize>, current: usize) {
-    debug_assert!(current <= nfa.states.len());
-    if current == nfa.states.len() {
-        return;
+/// The transitions matching a single character for all non-subexpression matchables in `m`
+pub(crate) fn class_transitions(m: &InvertibleMatchable) -> Vec<TransitionType> {
+    let mut ranges = Vec::new();
+    for matchable in m.matchable().matchables() {
+        match matchable {
+            Matchable::Char(c) => ranges.push((*c as u32, *c as u32)),
+            Matchable::Range(a, b) => ranges.push((*a as u32, *b as u32)),
+            Matchable::Any => ranges.push((0, char::MAX as u32)),
+            Matchable::Subexpression(_) => {
+                assert!(!*m.inverted(), "Only characters and ranges can be inverted");
+            }
+        }
+    }
+
+    if !*m.inverted() {
+        return ranges.into_iter().filter_map(|(a, b)| codepoint_transition(a, b)).collect();
     }
-    for (ts, ns) in &nfa.states[current].transitions {
-        if ts.is_none() {
-           states.insert(*ns);
-            gather_epsilon(nfa, states, *ns);
+
+    // Everything not in `ranges`
+    ranges.sort();
+    let mut transitions = Vec::new();
+    let mut next = 0;
+    for (a, b) in ranges {
+        if a > next {
+            transitions.extend(codepoint_transition(next, a - 1));
         }
+        next = next.max(b + 1);
     }
+    if next <= char::MAX as u32 {
+        transitions.extend(codepoint_transition(next, char::MAX as u32));
+    }
+    transitions
 }
 
-fn gather_transitions(self_states: &HashSet<usize>, base_state: usize, nfa: &NFA, current_pos: usize, transitions: &mut TransitionHolder) {
-    if current_pos != base_state && self_states.contains(&current_pos) {
-        return;
+/// A transition matching the (inclusive) codepoint range `a..=b`, skipping surrogates at either end. `None` if
+/// the range only consists of surrogates.
+pub(crate) fn codepoint_transition(a: u32, b: u32) -> Option<TransitionType> {
+    let surrogates = 0xD800..=0xDFFF;
+    if surrogates.contains(&a) && surrogates.contains(&b) {
+        return None;
+    }
+    let a = if surrogates.contains(&a) { 0xE000 } else { a };
+    let b = if surrogates.contains(&b) { 0xD7FF } else { b };
+    let (a, b) = (char::from_u32(a).unwrap(), char::from_u32(b).unwrap());
+    if a == b {
+        Some(Single(char_to_utf8(a)))
     }
-    debug_assert!(current_pos <= nfa.states.len());
-    if current_pos == nfa.states.len() {
-        return;
+    else {
+        Some(Range(char_to_utf8(a), char_to_utf8(b)))
     }
-    for (tt, ns) in &nfa.states[current_pos].transitions {
-        if let Some(tt) = tt {
-            let mut epsilon_states = HashSet::new();
-            epsilon_states.insert(*ns);
-            gather_epsilon(nfa, &mut epsilon_states, *ns);
-            transitions.add_transitions(*tt, epsilon_states);
+}
+
+/// The epsilon closure of every NFA state, including the success state at `nfa.states.len()`, as sorted sets
+fn epsilon_closures(nfa: &NFA) -> Vec<Vec<usize>> {
+    let mut closures = Vec::with_capacity(nfa.states.len() + 1);
+    let mut seen = vec![false; nfa.states.len() + 1];
+    for start in 0..=nfa.states.len() {
+        let mut closure = vec![start];
+        seen[start] = true;
+        let mut i = 0;
+        while i < closure.len() {
+            if let Some(state) = nfa.states.get(closure[i]) {
+                for (tt, ns) in &state.transitions {
+                    if tt.is_none() && !seen[*ns] {
+                        seen[*ns] = true;
+                        closure.push(*ns);
+                    }
+                }
+            }
+            i += 1;
         }
-        else {
-            gather_transitions(self_states, base_state, nfa, *ns, transitions);
+        for s in &closure {
+            seen[*s] = false;
         }
+        closure.sort_unstable();
+        closures.push(closure);
     }
+    closures
 }
 
-pub fn to_dfa(nfa: NFA) -> DFA {
-    let mut start_states = HashSet::new();
-    start_states.insert(0);
-    gather_epsilon(&nfa, &mut start_states, 0);
+/// Precomputed data for repeatedly stepping sets of NFA states
+pub(crate) struct SubsetBuilder {
+    /// The NFA's success state
+    success: usize,
+    closures: Vec<Vec<usize>>,
+    /// Non-epsilon transitions of each state as (first codepoint, last codepoint, destination)
+    char_transitions: Vec<Vec<(u32, u32, usize)>>,
+}
 
-    let mut done: Vec<HashSet<usize>> = Vec::new();
-    let mut states: Vec<(HashSet<usize>, TransitionHolder)> = Vec::new();
+impl SubsetBuilder {
+    pub(crate) fn new(nfa: &NFA) -> SubsetBuilder {
+        let char_transitions = nfa.states.iter().map(|s| {
+            s.transitions.iter()
+                .filter_map(|(tt, ns)| tt.map(|tt| (tt, *ns)))
+                .flat_map(|(tt, ns)| tt.codepoints().into_iter().map(move |(a, b)| (a, b, ns)))
+                .collect()
+        }).collect();
 
-    let mut open_set = VecDeque::new();
-    open_set.push_back(start_states);
+        SubsetBuilder { success: nfa.states.len(), closures: epsilon_closures(nfa), char_transitions }
+    }
 
-    while let Some(set) = open_set.pop_front() {
-        let mut th = TransitionHolder::default();
-        for s in set.iter() {
-            gather_transitions(&set, *s, &nfa, *s, &mut th);
-        }
+    pub(crate) fn start(&self) -> Vec<usize> {
+        self.closures[0].clone()
+    }
 
-        done.push(set.clone());
+    pub(crate) fn is_success(&self, set: &[usize]) -> bool {
+        set.last() == Some(&self.success)
+    }
 
-        for (_, other) in th.inner.iter() {
-            if !done.contains(other) && !open_set.contains(other) {
-                open_set.push_back(other.clone());
+    /// The sets reachable from `set` by a single character, as disjoint, sorted (inclusive) codepoint ranges
+    pub(crate) fn step(&self, set: &[usize]) -> Vec<(u32, u32, Vec<usize>)> {
+        let transitions = set.iter()
+            .filter_map(|s| self.char_transitions.get(*s))
+            .flatten()
+            .collect_vec();
+
+        // Split the alphabet at every point where any transition starts or ends
+        let mut boundaries = transitions.iter().flat_map(|(a, b, _)| [*a, b + 1]).collect_vec();
+        boundaries.sort_unstable();
+        boundaries.dedup();
+
+        let mut segments: Vec<Vec<usize>> = vec![Vec::new(); boundaries.len()];
+        for (a, b, ns) in transitions {
+            let first = boundaries.binary_search(a).unwrap();
+            let last = boundaries.binary_search(&(b + 1)).unwrap();
+            for segment in &mut segments[first..last] {
+                segment.extend_from_slice(&self.closures[*ns]);
             }
         }
 
-        let th = th.collapse_transitions();
+        let mut out: Vec<(u32, u32, Vec<usize>)> = Vec::new();
+        for (i, mut set) in segments.into_iter().enumerate() {
+            if set.is_empty() {
+                continue;
+            }
+            set.sort_unstable();
+            set.dedup();
+            let (a, b) = (boundaries[i], boundaries[i + 1] - 1);
+
+            if let Some(last) = out.last_mut() {
+                if last.1 + 1 == a && last.2 == set {
+                    last.1 = b;
+                    continue;
+                }
+            }
+            out.push((a, b, set));
+        }
+        out
+    }
+}
 
-        states.push((set, th));
+pub fn to_dfa(nfa: NFA) -> DFA {
+    match try_to_dfa(nfa, &CompileOptions::default()) {
+        Ok(dfa) => dfa,
+        Err(e) => panic!("{e}"),
     }
+}
+
+/// Subset construction producing a DFA whose states' transitions are disjoint
+pub fn try_to_dfa(nfa: NFA, options: &CompileOptions) -> Result<DFA, CompileError> {
+    subset_construction(&nfa, options).map(|(dfa, _)| dfa)
+}
+
+/// Subset construction, also returning the set of NFA states each DFA state corresponds to
+pub(crate) fn subset_construction(nfa: &NFA, options: &CompileOptions) -> Result<(DFA, Vec<Vec<usize>>), CompileError> {
+    let builder = SubsetBuilder::new(nfa);
+
+    let mut ids: HashMap<Vec<usize>, usize> = HashMap::new();
+    let mut sets = vec![builder.start()];
+    ids.insert(sets[0].clone(), 0);
 
     let mut dfa = DFA::default();
+    while dfa.transitions.len() < sets.len() {
+        let set = &sets[dfa.transitions.len()];
+        let success = builder.is_success(set);
 
-    for (ss, ts) in &states {
-        let success = ss.contains(&nfa.states.len());
         let mut transitions = Vec::new();
-
-        for (tt, dst) in &ts.inner {
-            let idx = states.iter().find_position(|(ss, _)| dst == ss).unwrap().0;
-            transitions.push((*tt, idx));
+        for (a, b, next) in builder.step(set) {
+            let Some(tt) = codepoint_transition(a, b) else { continue; };
+            let id = match ids.get(&next) {
+                Some(id) => *id,
+                None => {
+                    if sets.len() as u64 >= options.max_dfa_states {
+                        return Err(CompileError::TooManyDfaStates { limit: options.max_dfa_states });
+                    }
+                    ids.insert(next.clone(), sets.len());
+                    sets.push(next);
+                    sets.len() - 1
+                }
+            };
+            transitions.push((tt, id));
         }
 
         dfa.transitions.push((success, transitions));
     }
 
-    dfa
+    Ok((dfa, sets))
 }
 
-pub fn test_nfa(input: &NFA, s: &str) -> bool {
-    nfa_ant(input, 0, 0, s)
+/// Set of NFA states (including the success state) with insertion order, used as a Pike VM thread list
+struct ThreadList {
+    dense: Vec<usize>,
+    /// Position of each state in `dense`, only valid if it points back to the state
+    sparse: Vec<usize>,
 }
 
-fn nfa_ant(nfa: &NFA, nfa_pos: usize, str_pos: usize, s: &str) -> bool {
-    // println!("{}", nfa_pos);
-    debug_assert!(nfa_pos <= nfa.states.len());
-    if nfa_pos == nfa.states.len() {
-        return str_pos == s.len();
+impl ThreadList {
+    fn new(states: usize) -> ThreadList {
+        ThreadList { dense: Vec::with_capacity(states), sparse: vec![0; states] }
     }
-    let current_state = &nfa.states[nfa_pos];
 
-    for (tt, ns) in &current_state.transitions {
-        let ns = *ns;
-        let r = if let Some(tt) = tt {
-            if str_pos == s.bytes().len() {
-                return false;
-            }
-            let (next_char, new_str_pos) = next_char(s, str_pos);
-            debug_assert!(new_str_pos > str_pos);
+    fn contains(&self, state: usize) -> bool {
+        self.dense.get(self.sparse[state]) == Some(&state)
+    }
 
-            match tt {
-                Single(c) => {
-                    if *c == next_char { nfa_ant(nfa, ns, new_str_pos, s) }
-                    else { false }
-                }
-                Range(a, b) => {
-                    if next_char >= *a && next_char <= *b { nfa_ant(nfa, ns, new_str_pos, s) }
-                    else { false }
-                }
-                ExcludeRange(a, b) => {
-                    if *a >= next_char || *b <= next_char { nfa_ant(nfa, ns, new_str_pos, s) }
-                    else { false }
+    /// Adds `state` and everything reachable from it through epsilon transitions
+    fn add_closure(&mut self, nfa: &NFA, state: usize, stack: &mut Vec<usize>) {
+        stack.push(state);
+        while let Some(s) = stack.pop() {
+            if self.contains(s) {
+                continue;
+            }
+            self.sparse[s] = self.dense.len();
+            self.dense.push(s);
+
+            if let Some(state) = nfa.states.get(s) {
+                // Reversed so that transitions are followed in order
+                for (tt, ns) in state.transitions.iter().rev() {
+                    if tt.is_none() && !self.contains(*ns) {
+                        stack.push(*ns);
+                    }
                 }
-                Any => {
-                    nfa_ant(nfa, ns, new_str_pos, s)
+            }
+        }
+    }
+
+    fn clear(&mut self) {
+        self.dense.clear();
+    }
+}
+
+/// Simulates the NFA on `s` by tracking every state it could be in at once (a Pike VM), taking time linear in
+/// the length of `s` regardless of the shape of the NFA
+pub fn test_nfa(input: &NFA, s: &str) -> bool {
+    let len = input.states.len() + 1;
+    let mut current = ThreadList::new(len);
+    let mut next = ThreadList::new(len);
+    let mut stack = Vec::new();
+
+    current.add_closure(input, 0, &mut stack);
+
+    let mut pos = 0;
+    while pos < s.len() && !current.dense.is_empty() {
+        let (c, new_pos) = next_char(s, pos);
+        pos = new_pos;
+
+        for &state in &current.dense {
+            let Some(state) = input.states.get(state) else { continue; };
+            for (tt, ns) in &state.transitions {
+                if tt.is_some_and(|tt| tt.matches(c)) {
+                    next.add_closure(input, *ns, &mut stack);
                 }
             }
         }
-        else {
-            nfa_ant(nfa, ns, str_pos, s)
-        };
-        if r { return true }
+
+        std::mem::swap(&mut current, &mut next);
+        next.clear();
     }
-    false
-}
\ No newline at end of file
+
+    pos == s.len() && current.contains(input.states.len())
+}
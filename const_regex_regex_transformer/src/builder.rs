@@ -0,0 +1,211 @@
+use crate::automata::bounds_repetition;
+use crate::regex::{ChainedMatchable, InvertibleMatchable, Matchable, Repetition, Span, UnionMatchables};
+use std::ops::{Bound, RangeBounds, RangeInclusive};
+
+/// A set of characters for use with [`Pattern::class`] (the equivalent of `[...]` / `[^...]`)
+#[derive(Debug, Clone, Default)]
+pub struct Class {
+    inverted: bool,
+    matchables: Vec<Matchable>
+}
+
+impl Class {
+    pub fn new() -> Class {
+        Class::default()
+    }
+
+    pub fn char(mut self, c: char) -> Class {
+        self.matchables.push(Matchable::Char(c));
+        self
+    }
+
+    pub fn chars(mut self, s: &str) -> Class {
+        self.matchables.extend(s.chars().map(Matchable::Char));
+        self
+    }
+
+    pub fn range(mut self, range: RangeInclusive<char>) -> Class {
+        let (a, b) = range.into_inner();
+        assert!(a <= b, "Invalid character range {a:?}-{b:?}");
+        self.matchables.push(if a == b { Matchable::Char(a) } else { Matchable::Range(a, b) });
+        self
+    }
+
+    /// Matches every character not in the class
+    pub fn negate(mut self) -> Class {
+        self.inverted = !self.inverted;
+        self
+    }
+}
+
+impl From<char> for Class {
+    fn from(c: char) -> Self {
+        Class::new().char(c)
+    }
+}
+
+impl From<RangeInclusive<char>> for Class {
+    fn from(range: RangeInclusive<char>) -> Self {
+        Class::new().range(range)
+    }
+}
+
+/// Programmatic construction of [`ChainedMatchable`]s without going through the regex syntax
+///
+/// ```
+/// # use const_regex_regex_transformer::builder::Pattern;
+/// // hell[a-z]+o+
+/// let p = Pattern::lit("hell")
+///     .then(Pattern::class('a'..='z').repeat(1..))
+///     .then(Pattern::char('o').repeat(1..));
+/// let regex = p.build();
+/// ```
+#[derive(Debug, Clone)]
+pub struct Pattern {
+    inner: ChainedMatchable
+}
+
+impl Pattern {
+    fn atom(matchables: Vec<Matchable>, inverted: bool) -> Pattern {
+        Pattern {
+            inner: ChainedMatchable {
+                matchables: InvertibleMatchable {
+                    inverted,
+                    matchable: UnionMatchables::unspanned(matchables),
+                },
+                repetition: Repetition::One,
+                next: None,
+                span: Span::default(),
+                repetition_span: Span::default(),
+            }
+        }
+    }
+
+    /// Matches `s` literally. `s` must not be empty.
+    pub fn lit(s: &str) -> Pattern {
+        let mut chars = s.chars().rev();
+        let last = chars.next().expect("Literal patterns can't be empty");
+        let mut inner = Pattern::char(last).inner;
+        for c in chars {
+            let mut p = Pattern::char(c).inner;
+            p.next = Some(Box::new(inner));
+            inner = p;
+        }
+        Pattern { inner }
+    }
+
+    pub fn char(c: char) -> Pattern {
+        Pattern::atom(vec![Matchable::Char(c)], false)
+    }
+
+    /// Matches any single character (`.`)
+    pub fn any() -> Pattern {
+        Pattern::atom(vec![Matchable::Any], false)
+    }
+
+    /// Matches a single character from `class`, e.g. `Pattern::class('a'..='z')`
+    pub fn class(class: impl Into<Class>) -> Pattern {
+        let class = class.into();
+        assert!(!class.matchables.is_empty(), "Character classes can't be empty");
+        Pattern::atom(class.matchables, class.inverted)
+    }
+
+    /// Matches `self` followed by `next`
+    pub fn then(self, next: Pattern) -> Pattern {
+        let mut inner = self.into_sequence_item();
+        let mut last = &mut inner;
+        while last.next.is_some() {
+            last = last.next.as_mut().unwrap();
+        }
+        last.next = Some(Box::new(next.into_sequence_item()));
+        Pattern { inner }
+    }
+
+    /// Matches either `self` or `other`
+    pub fn or(self, other: Pattern) -> Pattern {
+        let mut branches = Vec::new();
+        for p in [self, other] {
+            if p.is_alternation() {
+                branches.extend(p.inner.matchables.matchable.matchables);
+            }
+            else {
+                branches.push(Matchable::Subexpression(p.inner));
+            }
+        }
+        Pattern::atom(branches, false)
+    }
+
+    /// Repeats `self` a number of times within `range`, e.g. `repeat(1..)` for `+` or `repeat(2..=4)` for `{2,4}`
+    pub fn repeat(self, range: impl RangeBounds<u64>) -> Pattern {
+        let min = match range.start_bound() {
+            Bound::Included(x) => *x,
+            Bound::Excluded(x) => x.checked_add(1).expect("Empty repetition range"),
+            Bound::Unbounded => 0,
+        };
+        let max = match range.end_bound() {
+            Bound::Included(x) => Some(*x),
+            Bound::Excluded(x) => Some(x.checked_sub(1).expect("Empty repetition range")),
+            Bound::Unbounded => None,
+        };
+
+        assert!(max.is_none_or(|max| min <= max), "Empty repetition range");
+
+        let mut inner = self.into_atom();
+        inner.repetition = bounds_repetition(min, max);
+        Pattern { inner }
+    }
+
+    /// `*`
+    pub fn star(self) -> Pattern {
+        self.repeat(..)
+    }
+
+    /// `+`
+    pub fn plus(self) -> Pattern {
+        self.repeat(1..)
+    }
+
+    /// `?`
+    pub fn optional(self) -> Pattern {
+        self.repeat(..=1)
+    }
+
+    /// `{n}`
+    pub fn times(self, n: u64) -> Pattern {
+        self.repeat(n..=n)
+    }
+
+    pub fn build(self) -> ChainedMatchable {
+        self.inner
+    }
+
+    fn is_alternation(&self) -> bool {
+        self.inner.is_alternation()
+    }
+
+    /// A pattern that can be followed by other items without changing its meaning
+    fn into_sequence_item(self) -> ChainedMatchable {
+        if self.is_alternation() {
+            Pattern::atom(vec![Matchable::Subexpression(self.inner)], false).inner
+        }
+        else {
+            self.inner
+        }
+    }
+
+    /// A pattern consisting of a single, unrepeated item that a repetition can be applied to
+    fn into_atom(self) -> ChainedMatchable {
+        if !self.is_alternation() && self.inner.next.is_none() && self.inner.repetition == Repetition::One {
+            self.inner
+        }
+        else {
+            Pattern::atom(vec![Matchable::Subexpression(self.inner)], false).inner
+        }
+    }
+}
+
+impl From<Pattern> for ChainedMatchable {
+    fn from(p: Pattern) -> Self {
+        p.build()
+    }
+}
@@ -0,0 +1,290 @@
+use crate::builder::{Class, Pattern};
+use crate::automata::{class_transitions, explore, repetition_bounds, sequence_size, DFA};
+use crate::error::CompileError;
+use crate::options::CompileOptions;
+use crate::regex::{ChainedMatchable, InvertibleMatchable, Matchable};
+use std::rc::Rc;
+
+/// A regular expression in a normalised form suited to taking Brzozowski derivatives. Unlike
+/// [`ChainedMatchable`] it can express intersection and complement. Build it with the constructor functions,
+/// which keep it normalised (e.g. `Or`s are flattened, sorted and deduplicated) - this is what guarantees that
+/// [`to_dfa`] terminates.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Re {
+    /// Matches nothing
+    Empty,
+    /// Matches only the empty string
+    Epsilon,
+    /// A single character from a set of sorted, disjoint (inclusive) codepoint ranges
+    Set(Vec<(u32, u32)>),
+    Concat(Rc<Re>, Rc<Re>),
+    Star(Rc<Re>),
+    Or(Vec<Re>),
+    And(Vec<Re>),
+    Not(Rc<Re>),
+}
+
+impl Re {
+    /// Matches any string
+    pub fn universal() -> Re {
+        Re::Not(Rc::new(Re::Empty))
+    }
+
+    pub fn set(mut ranges: Vec<(u32, u32)>) -> Re {
+        ranges.sort_unstable();
+        let mut merged: Vec<(u32, u32)> = Vec::new();
+        for (a, b) in ranges {
+            match merged.last_mut() {
+                Some((_, end)) if a <= *end + 1 => *end = (*end).max(b),
+                _ => merged.push((a, b)),
+            }
+        }
+
+        if merged.is_empty() { Re::Empty } else { Re::Set(merged) }
+    }
+
+    pub fn concat(a: Re, b: Re) -> Re {
+        match (a, b) {
+            (Re::Empty, _) | (_, Re::Empty) => Re::Empty,
+            (Re::Epsilon, b) => b,
+            (a, Re::Epsilon) => a,
+            // Keep concatenations right-associative
+            (Re::Concat(a1, a2), b) => Re::concat((*a1).clone(), Re::concat((*a2).clone(), b)),
+            (a, b) => Re::Concat(Rc::new(a), Rc::new(b)),
+        }
+    }
+
+    pub fn star(a: Re) -> Re {
+        match a {
+            Re::Empty | Re::Epsilon => Re::Epsilon,
+            a @ Re::Star(_) => a,
+            a => Re::Star(Rc::new(a)),
+        }
+    }
+
+    pub fn complement(a: Re) -> Re {
+        match a {
+            Re::Not(a) => (*a).clone(),
+            a => Re::Not(Rc::new(a)),
+        }
+    }
+
+    pub fn or(items: impl IntoIterator<Item=Re>) -> Re {
+        let mut flat = Vec::new();
+        let mut chars = Vec::new();
+        for r in items {
+            match r {
+                Re::Or(rs) => flat.extend(rs),
+                r => flat.push(r),
+            }
+        }
+
+        let mut out = Vec::new();
+        for r in flat {
+            match r {
+                Re::Empty => {}
+                Re::Set(s) => chars.extend(s),
+                r if r == Re::universal() => return r,
+                r => out.push(r),
+            }
+        }
+        if !chars.is_empty() {
+            out.push(Re::set(chars));
+        }
+
+        out.sort_unstable();
+        out.dedup();
+        match out.len() {
+            0 => Re::Empty,
+            1 => out.pop().unwrap(),
+            _ => Re::Or(out),
+        }
+    }
+
+    pub fn and(items: impl IntoIterator<Item=Re>) -> Re {
+        let mut flat = Vec::new();
+        for r in items {
+            match r {
+                Re::And(rs) => flat.extend(rs),
+                r => flat.push(r),
+            }
+        }
+
+        let mut out = Vec::new();
+        let mut chars: Option<Vec<(u32, u32)>> = None;
+        for r in flat {
+            match r {
+                Re::Empty => return Re::Empty,
+                Re::Set(s) => chars = Some(match chars {
+                    None => s,
+                    Some(c) => intersect_ranges(&c, &s),
+                }),
+                r if r == Re::universal() => {}
+                r => out.push(r),
+            }
+        }
+        if let Some(chars) = chars {
+            match Re::set(chars) {
+                Re::Empty => return Re::Empty,
+                s => out.push(s),
+            }
+        }
+
+        out.sort_unstable();
+        out.dedup();
+        match out.len() {
+            0 => Re::universal(),
+            1 => out.pop().unwrap(),
+            _ => Re::And(out),
+        }
+    }
+
+    /// Strings matched by `a` but not `b`
+    pub fn difference(a: Re, b: Re) -> Re {
+        Re::and([a, Re::complement(b)])
+    }
+
+    pub fn from_regex(regex: &ChainedMatchable) -> Re {
+        regex.iter().fold(Re::Epsilon, |r, item| {
+            let m = Re::from_matchable(item.matchables());
+            let (min, max) = repetition_bounds(item.repetition());
+
+            let mut repeated = Re::Epsilon;
+            for _ in 0..min {
+                repeated = Re::concat(repeated, m.clone());
+            }
+            let tail = match max {
+                None => Re::star(m),
+                Some(max) => {
+                    // x{0,3} as (x(x(x)?)?)?
+                    let mut tail = Re::Epsilon;
+                    for _ in min..max {
+                        tail = Re::or([Re::Epsilon, Re::concat(m.clone(), tail)]);
+                    }
+                    tail
+                }
+            };
+            Re::concat(r, Re::concat(repeated, tail))
+        })
+    }
+
+    fn from_matchable(m: &InvertibleMatchable) -> Re {
+        let chars = class_transitions(m).iter().flat_map(|t| t.codepoints()).collect();
+        let subexpressions = m.matchable().matchables().iter().filter_map(|m| match m {
+            Matchable::Subexpression(s) => Some(Re::from_regex(s)),
+            _ => None,
+        });
+        Re::or(std::iter::once(Re::set(chars)).chain(subexpressions))
+    }
+
+    pub fn is_nullable(&self) -> bool {
+        match self {
+            Re::Empty | Re::Set(_) => false,
+            Re::Epsilon | Re::Star(_) => true,
+            Re::Concat(a, b) => a.is_nullable() && b.is_nullable(),
+            Re::Or(rs) => rs.iter().any(Re::is_nullable),
+            Re::And(rs) => rs.iter().all(Re::is_nullable),
+            Re::Not(a) => !a.is_nullable(),
+        }
+    }
+
+    /// The strings `s` such that `c` followed by `s` is matched by `self`
+    pub fn derivative(&self, c: u32) -> Re {
+        match self {
+            Re::Empty | Re::Epsilon => Re::Empty,
+            Re::Set(s) => {
+                if s.iter().any(|(a, b)| *a <= c && c <= *b) { Re::Epsilon } else { Re::Empty }
+            }
+            Re::Concat(a, b) => {
+                let d = Re::concat(a.derivative(c), (**b).clone());
+                if a.is_nullable() {
+                    Re::or([d, b.derivative(c)])
+                }
+                else {
+                    d
+                }
+            }
+            Re::Star(a) => Re::concat(a.derivative(c), self.clone()),
+            Re::Or(rs) => Re::or(rs.iter().map(|r| r.derivative(c))),
+            Re::And(rs) => Re::and(rs.iter().map(|r| r.derivative(c))),
+            Re::Not(a) => Re::complement(a.derivative(c)),
+        }
+    }
+
+    /// The equivalent [`Pattern`], or `None` if there isn't one: for `Empty` and `Epsilon`, which can't be written
+    /// in regex syntax, and for anything containing `And` or `Not`.
+    pub(crate) fn to_pattern(&self) -> Option<Pattern> {
+        match self {
+            Re::Empty | Re::Epsilon => None,
+            Re::Set(s) => {
+                let class = s.iter().fold(Class::new(), |class, (a, b)| {
+                    class.range(char::from_u32(*a).unwrap()..=char::from_u32(*b).unwrap())
+                });
+                Some(Pattern::class(class))
+            }
+            Re::Concat(a, b) => Some(a.to_pattern()?.then(b.to_pattern()?)),
+            Re::Star(a) => Some(a.to_pattern()?.star()),
+            Re::Or(rs) => {
+                let branches = rs.iter().filter(|r| **r != Re::Epsilon).map(Re::to_pattern).collect::<Option<Vec<_>>>()?;
+                let p = branches.into_iter().reduce(Pattern::or)?;
+                Some(if rs.contains(&Re::Epsilon) { p.optional() } else { p })
+            }
+            Re::And(_) | Re::Not(_) => None,
+        }
+    }
+
+    /// Adds the points at which the derivative of `self` can change to `boundaries`
+    fn boundaries(&self, boundaries: &mut Vec<u32>) {
+        match self {
+            Re::Empty | Re::Epsilon => {}
+            Re::Set(s) => {
+                for (a, b) in s {
+                    boundaries.push(*a);
+                    boundaries.push(b + 1);
+                }
+            }
+            Re::Concat(a, b) => {
+                a.boundaries(boundaries);
+                if a.is_nullable() {
+                    b.boundaries(boundaries);
+                }
+            }
+            Re::Star(a) | Re::Not(a) => a.boundaries(boundaries),
+            Re::Or(rs) | Re::And(rs) => rs.iter().for_each(|r| r.boundaries(boundaries)),
+        }
+    }
+}
+
+fn intersect_ranges(a: &[(u32, u32)], b: &[(u32, u32)]) -> Vec<(u32, u32)> {
+    let mut out = Vec::new();
+    for (a1, a2) in a {
+        for (b1, b2) in b {
+            let (lo, hi) = ((*a1).max(*b1), (*a2).min(*b2));
+            if lo <= hi {
+                out.push((lo, hi));
+            }
+        }
+    }
+    out
+}
+
+/// Builds a DFA directly from `regex` using Brzozowski derivatives, with the same size checks as
+/// [`try_to_nfa`](crate::automata::try_to_nfa) followed by [`try_to_dfa`](crate::automata::try_to_dfa)
+pub fn try_regex_to_dfa(regex: &ChainedMatchable, options: &CompileOptions) -> Result<DFA, CompileError> {
+    let required = sequence_size(regex).saturating_add(1);
+    if required > options.max_nfa_states {
+        return Err(CompileError::TooManyNfaStates { limit: options.max_nfa_states, required });
+    }
+    to_dfa(Re::from_regex(regex), options)
+}
+
+/// Builds a DFA whose states are the (normalised) derivatives of `re`
+pub fn to_dfa(re: Re, options: &CompileOptions) -> Result<DFA, CompileError> {
+    explore(
+        re,
+        Re::is_nullable,
+        |re, boundaries| re.boundaries(boundaries),
+        |re, c| Some(re.derivative(c)).filter(|d| *d != Re::Empty),
+        options.max_dfa_states,
+    )
+}
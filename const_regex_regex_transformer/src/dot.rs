@@ -0,0 +1,98 @@
+use crate::automata::{TransitionType, DFA, NFA};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+impl NFA {
+    /// The NFA in Graphviz DOT format, with epsilon transitions labelled `ε` and the accepting state
+    /// double-circled
+    pub fn to_dot(&self) -> String {
+        let mut edges: BTreeMap<(usize, usize), Vec<Option<TransitionType>>> = BTreeMap::new();
+        for (i, state) in self.states.iter().enumerate() {
+            for (t, ns) in &state.transitions {
+                edges.entry((i, *ns)).or_default().push(*t);
+            }
+        }
+
+        let edges = edges.into_iter().map(|(k, ts)| {
+            let epsilon = ts.contains(&None).then(|| "ε".to_string());
+            let chars = ts.into_iter().flatten().collect::<Vec<_>>();
+            let label = epsilon.into_iter().chain((!chars.is_empty()).then(|| char_label(&chars))).collect::<Vec<_>>();
+            (k, label.join(", "))
+        });
+        graph("NFA", &[self.states.len()], edges)
+    }
+}
+
+impl DFA {
+    /// The DFA in Graphviz DOT format, with accepting states double-circled
+    pub fn to_dot(&self) -> String {
+        let mut edges: BTreeMap<(usize, usize), Vec<TransitionType>> = BTreeMap::new();
+        for (i, (_, ts)) in self.transitions.iter().enumerate() {
+            for (t, ns) in ts {
+                edges.entry((i, *ns)).or_default().push(*t);
+            }
+        }
+
+        let accepting = (0..self.transitions.len()).filter(|s| self.transitions[*s].0).collect::<Vec<_>>();
+        graph("DFA", &accepting, edges.into_iter().map(|(k, ts)| (k, char_label(&ts))))
+    }
+}
+
+fn graph(name: &str, accepting: &[usize], edges: impl Iterator<Item=((usize, usize), String)>) -> String {
+    let mut dot = format!("digraph {name} {{\n    rankdir=LR;\n    node [shape=circle];\n    start [shape=point];\n    start -> 0;\n");
+    for s in accepting {
+        writeln!(dot, "    {s} [shape=doublecircle];").unwrap();
+    }
+    for ((from, to), label) in edges {
+        writeln!(dot, "    {from} -> {to} [label=\"{label}\"];").unwrap();
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+/// A readable label for the characters matched by `ts`, e.g. `a-z, _` or `^\n` (everything other than `\n`)
+fn char_label(ts: &[TransitionType]) -> String {
+    let mut ranges = ts.iter().flat_map(|t| t.codepoints()).collect::<Vec<_>>();
+    ranges.sort_unstable();
+
+    let mut merged: Vec<(u32, u32)> = Vec::new();
+    for (a, b) in ranges {
+        match merged.last_mut() {
+            Some((_, end)) if a <= *end + 1 || (*end, a) == (0xD7FF, 0xE000) => *end = (*end).max(b),
+            _ => merged.push((a, b)),
+        }
+    }
+
+    // Show mostly complete sets as what they exclude
+    if merged.first().is_some_and(|(a, _)| *a == 0) && merged.last().is_some_and(|(_, b)| *b == char::MAX as u32) {
+        let excluded = merged.windows(2).filter_map(|w| without_surrogates((w[0].1 + 1, w[1].0 - 1))).collect::<Vec<_>>();
+        if excluded.is_empty() {
+            return "any".to_string();
+        }
+        return format!("^{}", range_list(&excluded));
+    }
+    range_list(&merged)
+}
+
+/// `(a, b)` shrunk so that it doesn't start or end with a surrogate, or `None` if that leaves nothing
+fn without_surrogates((a, b): (u32, u32)) -> Option<(u32, u32)> {
+    let surrogates = 0xD800..=0xDFFF;
+    let a = if surrogates.contains(&a) { 0xE000 } else { a };
+    let b = if surrogates.contains(&b) { 0xD7FF } else { b };
+    (a <= b).then_some((a, b))
+}
+
+fn range_list(ranges: &[(u32, u32)]) -> String {
+    let char = |c: u32| match char::from_u32(c).unwrap() {
+        '"' => "\\\"".to_string(),
+        '\\' => "\\\\".to_string(),
+        ' ' => "' '".to_string(),
+        // Escaped twice so that DOT shows e.g. `\n` rather than a new line
+        c if c.is_control() => c.escape_debug().to_string().replace('\\', "\\\\"),
+        c => c.to_string(),
+    };
+
+    ranges.iter().filter_map(|r| without_surrogates(*r)).map(|(a, b)| {
+        if a == b { char(a) } else { format!("{}-{}", char(a), char(b)) }
+    }).collect::<Vec<_>>().join(", ")
+}
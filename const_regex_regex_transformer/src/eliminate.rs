@@ -0,0 +1,76 @@
+use crate::automata::DFA;
+use crate::derivative::Re;
+use crate::regex::ChainedMatchable;
+use crate::simplify::simplify;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Automaton whose edges are labelled with regexes
+struct Graph {
+    out: Vec<BTreeMap<usize, Re>>,
+    incoming: Vec<BTreeSet<usize>>,
+}
+
+impl Graph {
+    fn add(&mut self, from: usize, to: usize, re: Re) {
+        let existing = self.out[from].remove(&to).unwrap_or(Re::Empty);
+        self.out[from].insert(to, Re::or([existing, re]));
+        self.incoming[to].insert(from);
+    }
+
+    /// Removes state `k`, replacing every path through it with a direct edge
+    fn eliminate(&mut self, k: usize) {
+        let repeat = Re::star(self.out[k].remove(&k).unwrap_or(Re::Empty));
+        self.incoming[k].remove(&k);
+
+        let targets = std::mem::take(&mut self.out[k]);
+        for j in targets.keys() {
+            self.incoming[*j].remove(&k);
+        }
+        for i in std::mem::take(&mut self.incoming[k]) {
+            let before = self.out[i].remove(&k).unwrap();
+            for (j, after) in &targets {
+                self.add(i, *j, Re::concat(before.clone(), Re::concat(repeat.clone(), after.clone())));
+            }
+        }
+    }
+}
+
+impl DFA {
+    /// The (simplified) AST of a regex accepting the same strings as the DFA, built by state elimination. `None` if
+    /// the DFA accepts nothing or only the empty string, neither of which can be written in regex syntax.
+    ///
+    /// ```
+    /// # use const_regex_regex_transformer::{to_automata, to_regex};
+    /// let dfa = to_automata(to_regex("[a-z]+")).difference(&to_automata(to_regex("[a-z]*q")));
+    /// assert_eq!(dfa.to_ast().unwrap().to_string(), "q*[a-pr-z]([a-pr-z]|q+[a-pr-z])*");
+    /// ```
+    pub fn to_ast(&self) -> Option<ChainedMatchable> {
+        let dfa = self.minimize();
+        let useful = dfa.useful_states();
+
+        // An extra start state leads to the DFA's start, and all accepting states lead to an extra end state
+        let (start, end) = (dfa.transitions.len(), dfa.transitions.len() + 1);
+        let mut graph = Graph { out: vec![BTreeMap::new(); end + 1], incoming: vec![BTreeSet::new(); end + 1] };
+        if useful[0] {
+            graph.add(start, 0, Re::Epsilon);
+        }
+        for (s, (success, ts)) in dfa.transitions.iter().enumerate().filter(|(s, _)| useful[*s]) {
+            if *success {
+                graph.add(s, end, Re::Epsilon);
+            }
+            for (t, ns) in ts.iter().filter(|(_, ns)| useful[*ns]) {
+                graph.add(s, *ns, Re::set(t.codepoints()));
+            }
+        }
+
+        let mut remaining = (0..dfa.transitions.len()).filter(|s| useful[*s]).collect::<BTreeSet<_>>();
+        while !remaining.is_empty() {
+            // Eliminating the state with the fewest paths through it first keeps the expressions small
+            let k = *remaining.iter().min_by_key(|k| graph.incoming[**k].len() * graph.out[**k].len()).unwrap();
+            remaining.remove(&k);
+            graph.eliminate(k);
+        }
+
+        graph.out[start].get(&end)?.to_pattern().map(|p| simplify(p.build()))
+    }
+}
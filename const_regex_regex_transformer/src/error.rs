@@ -0,0 +1,82 @@
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CompileError {
+    /// The NFA would need (at least) `required` states, more than the configured `limit`
+    TooManyNfaStates { limit: u64, required: u64 },
+    /// Determinising the NFA would create more than `limit` states
+    TooManyDfaStates { limit: u64 },
+}
+
+impl Display for CompileError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CompileError::TooManyNfaStates { limit, required } => write!(
+                f,
+                "Regex is too large: it expands to {} NFA states, but the limit is {limit}. \
+                Reduce the bounds of repetitions such as `{{n,m}}` or raise the limit.",
+                if *required == u64::MAX { "more than u64::MAX".to_string() } else { required.to_string() }
+            ),
+            CompileError::TooManyDfaStates { limit } => write!(
+                f,
+                "Regex is too large: its DFA has more than {limit} states. Simplify the regex or raise the limit."
+            ),
+        }
+    }
+}
+
+impl Error for CompileError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    /// The pattern isn't valid regex syntax at byte `offset`
+    Syntax { offset: usize },
+    /// The escape sequence at byte `offset` isn't supported, e.g. `\d`
+    UnsupportedEscape { offset: usize },
+    /// The `)` at byte `offset` has no matching `(`
+    UnmatchedParen { offset: usize },
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseError::Syntax { offset } => write!(f, "Invalid regex syntax at byte {offset}"),
+            ParseError::UnsupportedEscape { offset } => write!(f, "Unsupported escape sequence at byte {offset}"),
+            ParseError::UnmatchedParen { offset } => write!(f, "Unmatched ')' at byte {offset}"),
+        }
+    }
+}
+
+impl Error for ParseError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The data doesn't start with the DFA format's magic bytes
+    BadMagic,
+    /// The data was written by a newer, incompatible version of the format
+    UnsupportedVersion(u16),
+    /// The data ends early
+    Truncated,
+    /// The data doesn't match its checksum
+    ChecksumMismatch,
+    /// The data is well formed but doesn't describe a valid DFA
+    Invalid(&'static str),
+    /// The JSON couldn't be parsed
+    Json(String),
+}
+
+impl Display for DecodeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecodeError::BadMagic => write!(f, "Not a serialised DFA"),
+            DecodeError::UnsupportedVersion(v) => write!(f, "Unsupported DFA format version {v}"),
+            DecodeError::Truncated => write!(f, "Serialised DFA is truncated"),
+            DecodeError::ChecksumMismatch => write!(f, "Serialised DFA is corrupt: checksum mismatch"),
+            DecodeError::Invalid(reason) => write!(f, "Invalid serialised DFA: {reason}"),
+            DecodeError::Json(e) => write!(f, "Invalid DFA JSON: {e}"),
+        }
+    }
+}
+
+impl Error for DecodeError {}
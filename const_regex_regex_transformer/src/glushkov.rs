@@ -0,0 +1,143 @@
+use crate::automata::{class_transitions, repetition_bounds, NFAState, TransitionType, NFA};
+use crate::regex::{ChainedMatchable, InvertibleMatchable, Matchable};
+
+/// The positions a (sub)expression can start and end at, and whether it matches the empty string
+#[derive(Default)]
+struct Linearised {
+    nullable: bool,
+    first: Vec<usize>,
+    last: Vec<usize>,
+}
+
+impl Linearised {
+    fn empty() -> Linearised {
+        Linearised { nullable: true, ..Linearised::default() }
+    }
+}
+
+struct Glushkov {
+    /// The transitions leading into each position. Position `0` is the start state.
+    labels: Vec<Vec<TransitionType>>,
+    /// The positions that can follow each position
+    follow: Vec<Vec<usize>>,
+}
+
+/// Builds a Glushkov (position) automaton for `regex`. Every state other than the start state corresponds to a
+/// single occurrence of a character class, and all transitions into a state match that class.
+pub(crate) fn glushkov_nfa(regex: &ChainedMatchable) -> NFA {
+    let mut g = Glushkov { labels: vec![Vec::new()], follow: vec![Vec::new()] };
+    let l = g.sequence(regex);
+    g.follow[0] = l.first;
+
+    let success = g.labels.len();
+    let mut states = Vec::with_capacity(success);
+    for (p, follow) in g.follow.iter_mut().enumerate() {
+        follow.sort_unstable();
+        follow.dedup();
+
+        let mut state = NFAState::default();
+        for q in follow.iter() {
+            for t in &g.labels[*q] {
+                state.add_transition(Some(*t), *q);
+            }
+        }
+        if (p == 0 && l.nullable) || l.last.contains(&p) {
+            state.add_transition(None, success);
+        }
+        states.push(state);
+    }
+
+    NFA { states }
+}
+
+impl Glushkov {
+    fn position(&mut self, label: Vec<TransitionType>) -> usize {
+        self.labels.push(label);
+        self.follow.push(Vec::new());
+        self.labels.len() - 1
+    }
+
+    /// `a` followed by `b`
+    fn concat(&mut self, a: Linearised, b: Linearised) -> Linearised {
+        for l in &a.last {
+            self.follow[*l].extend_from_slice(&b.first);
+        }
+
+        let mut first = a.first;
+        if a.nullable {
+            first.extend_from_slice(&b.first);
+        }
+        let mut last = b.last;
+        if b.nullable {
+            last.extend_from_slice(&a.last);
+        }
+        Linearised { nullable: a.nullable && b.nullable, first, last }
+    }
+
+    fn union(a: Linearised, mut b: Linearised) -> Linearised {
+        let mut first = a.first;
+        first.append(&mut b.first);
+        let mut last = a.last;
+        last.append(&mut b.last);
+        Linearised { nullable: a.nullable || b.nullable, first, last }
+    }
+
+    fn sequence(&mut self, c: &ChainedMatchable) -> Linearised {
+        c.iter().fold(Linearised::empty(), |l, item| {
+            let i = self.item(item);
+            self.concat(l, i)
+        })
+    }
+
+    fn item(&mut self, item: &ChainedMatchable) -> Linearised {
+        let m = item.matchables();
+        let (min, max) = repetition_bounds(item.repetition());
+
+        let mut l = Linearised::empty();
+        for _ in 0..min {
+            let x = self.matchable(m);
+            l = self.concat(l, x);
+        }
+
+        let tail = match max {
+            None => {
+                let mut x = self.matchable(m);
+                for last in &x.last {
+                    self.follow[*last].extend_from_slice(&x.first);
+                }
+                x.nullable = true;
+                x
+            }
+            Some(max) => {
+                // x{0,3} as (x(x(x)?)?)? - this avoids every copy being followed by every other copy
+                let mut tail = Linearised::empty();
+                for _ in min..max {
+                    let x = self.matchable(m);
+                    tail = self.concat(x, tail);
+                    tail.nullable = true;
+                }
+                tail
+            }
+        };
+        self.concat(l, tail)
+    }
+
+    fn matchable(&mut self, m: &InvertibleMatchable) -> Linearised {
+        let transitions = class_transitions(m);
+        let mut l = if transitions.is_empty() {
+            Linearised { nullable: false, first: Vec::new(), last: Vec::new() }
+        }
+        else {
+            let p = self.position(transitions);
+            Linearised { nullable: false, first: vec![p], last: vec![p] }
+        };
+
+        for m in m.matchable().matchables() {
+            if let Matchable::Subexpression(s) = m {
+                let s = self.sequence(s);
+                l = Glushkov::union(l, s);
+            }
+        }
+        l
+    }
+}
@@ -0,0 +1,114 @@
+use crate::automata::{try_to_nfa, SubsetBuilder, NFA};
+use crate::error::CompileError;
+use crate::options::CompileOptions;
+use crate::regex::ChainedMatchable;
+use crate::simplify::simplify;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+struct LazyState {
+    set: Vec<usize>,
+    success: bool,
+    /// (first codepoint, last codepoint, destination) - `None` until the state is first left
+    transitions: Option<Vec<(u32, u32, Target)>>,
+}
+
+/// The destination of a transition, which is only added to the cache once the transition is taken
+enum Target {
+    Built(usize),
+    Unbuilt(Vec<usize>),
+}
+
+/// A runtime matcher that builds DFA states from an [`NFA`] while matching, instead of determinising it up
+/// front. Only states that inputs actually reach are built, and at most `capacity` are kept at once, so it can
+/// be used for regexes whose full DFA would be too large to generate.
+pub struct LazyDFA {
+    builder: SubsetBuilder,
+    capacity: usize,
+    ids: HashMap<Vec<usize>, usize>,
+    states: Vec<LazyState>,
+}
+
+impl LazyDFA {
+    /// `capacity` is at least 2, for the start state and the current one
+    pub fn new(nfa: NFA, capacity: usize) -> LazyDFA {
+        let builder = SubsetBuilder::new(&nfa);
+        let mut dfa = LazyDFA {
+            builder,
+            capacity: capacity.max(2),
+            ids: HashMap::new(),
+            states: Vec::new(),
+        };
+        dfa.reset();
+        dfa
+    }
+
+    /// Builds the NFA with [`try_to_nfa`], so [`Construction::Brzozowski`](crate::options::Construction::Brzozowski)
+    /// falls back to Thompson's construction
+    pub fn from_regex(regex: ChainedMatchable, capacity: usize, options: &CompileOptions) -> Result<LazyDFA, CompileError> {
+        Ok(LazyDFA::new(try_to_nfa(simplify(regex), options)?, capacity))
+    }
+
+    /// The number of DFA states currently cached
+    pub fn cached_states(&self) -> usize {
+        self.states.len()
+    }
+
+    pub fn test(&mut self, input: &str) -> bool {
+        let mut state = 0;
+        for c in input.chars() {
+            match self.next(state, c as u32) {
+                Some(next) => state = next,
+                None => return false,
+            }
+        }
+        self.states[state].success
+    }
+
+    /// Drops all cached states and re-adds the start state as state `0`
+    fn reset(&mut self) {
+        self.ids.clear();
+        self.states.clear();
+        self.intern(self.builder.start());
+    }
+
+    fn intern(&mut self, set: Vec<usize>) -> usize {
+        if let Some(id) = self.ids.get(&set) {
+            return *id;
+        }
+        let id = self.states.len();
+        self.ids.insert(set.clone(), id);
+        self.states.push(LazyState { success: self.builder.is_success(&set), set, transitions: None });
+        id
+    }
+
+    /// The state reached from `state` on `c`, building it first if needed. If that would exceed the capacity, the
+    /// cache is cleared (except for the start state) first, invalidating the ids of other states.
+    fn next(&mut self, state: usize, c: u32) -> Option<usize> {
+        let LazyState { set, transitions, .. } = &mut self.states[state];
+        let transitions = transitions.get_or_insert_with(|| {
+            self.builder.step(set).into_iter()
+                .map(|(a, b, set)| (a, b, Target::Unbuilt(set)))
+                .collect()
+        });
+        let i = transitions.binary_search_by(|(a, b, _)| {
+            if *b < c { Ordering::Less } else if *a > c { Ordering::Greater } else { Ordering::Equal }
+        }).ok()?;
+
+        let set = match &transitions[i].2 {
+            Target::Built(id) => return Some(*id),
+            Target::Unbuilt(set) => set.clone(),
+        };
+        let id = match self.ids.get(&set) {
+            Some(id) => *id,
+            None if self.states.len() >= self.capacity => {
+                // `state` doesn't survive the reset, so there's no edge left to record
+                self.reset();
+                return Some(self.intern(set));
+            }
+            None => self.intern(set),
+        };
+        self.states[state].transitions.as_mut().unwrap()[i].2 = Target::Built(id);
+        Some(id)
+    }
+}
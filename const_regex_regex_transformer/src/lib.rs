@@ -1,15 +1,60 @@
-use crate::automata::{to_dfa, to_nfa, DFA};
+use crate::automata::{to_dfa, to_nfa, try_to_dfa, try_to_nfa, DFA};
+use crate::error::{CompileError, ParseError};
+use crate::options::{CompileOptions, Construction};
 use crate::regex::{parse_regex, ChainedMatchable};
+use crate::simplify::simplify;
 
+pub mod analysis;
 pub mod automata;
+pub mod builder;
+pub mod derivative;
+mod dot;
+mod eliminate;
+pub mod error;
+mod glushkov;
+pub mod lazy;
+pub mod options;
+pub mod rank;
 pub mod regex;
+pub mod sample;
+pub mod serialize;
+pub mod set;
+pub mod simplify;
+pub mod visit;
 
 
 pub fn to_regex(regex: &str) -> ChainedMatchable {
-    let s = format!("({})", regex);
-    parse_regex(&s, true).unwrap().1
+    try_to_regex(regex).unwrap_or_else(|e| panic!("{e} in regex {regex:?}"))
+}
+
+pub fn try_to_regex(regex: &str) -> Result<ChainedMatchable, ParseError> {
+    let (r, m) = parse_regex(regex, false).map_err(|e| match e {
+        nom::Err::Error(e) | nom::Err::Failure(e) => {
+            let offset = regex.len() - e.input.len();
+            if e.code == nom::error::ErrorKind::Escaped {
+                // Escapes fail just after the `\`
+                ParseError::UnsupportedEscape { offset: offset - 1 }
+            }
+            else {
+                ParseError::Syntax { offset }
+            }
+        }
+        nom::Err::Incomplete(_) => ParseError::Syntax { offset: regex.len() },
+    })?;
+    if !r.is_empty() {
+        return Err(ParseError::UnmatchedParen { offset: regex.len() - r.len() });
+    }
+    Ok(m)
 }
 
 pub fn to_automata(regex: ChainedMatchable) -> DFA {
-    to_dfa(to_nfa(regex))
+    to_dfa(to_nfa(simplify(regex)))
+}
+
+pub fn try_to_automata(regex: ChainedMatchable, options: &CompileOptions) -> Result<DFA, CompileError> {
+    let regex = simplify(regex);
+    match options.construction {
+        Construction::Brzozowski => derivative::try_regex_to_dfa(&regex, options),
+        _ => try_to_dfa(try_to_nfa(regex, options)?, options),
+    }
 }
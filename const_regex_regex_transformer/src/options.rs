@@ -0,0 +1,36 @@
+/// How regexes are turned into automata
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum Construction {
+    /// Builds small sub-automata for each part of the regex and connects them with epsilon transitions
+    #[default]
+    Thompson,
+    /// Builds a position automaton with one state per character class occurrence and no epsilon transitions
+    /// (other than into the success state). May have more transitions than [`Construction::Thompson`] but makes
+    /// determinising it cheaper.
+    Glushkov,
+    /// Builds the DFA directly from the regex using Brzozowski derivatives, skipping the NFA. Produces close to
+    /// minimal DFAs. Functions that need an NFA use [`Construction::Thompson`] instead.
+    Brzozowski,
+}
+
+/// Limits and settings used when compiling a regex to an automaton
+#[derive(Debug, Clone)]
+pub struct CompileOptions {
+    /// The maximum number of NFA states a regex may expand to (e.g. through `{n,m}` repetitions) before
+    /// compilation is aborted with [`CompileError::TooManyNfaStates`](crate::error::CompileError::TooManyNfaStates)
+    pub max_nfa_states: u64,
+    /// The maximum number of states subset construction may create before compilation is aborted with
+    /// [`CompileError::TooManyDfaStates`](crate::error::CompileError::TooManyDfaStates)
+    pub max_dfa_states: u64,
+    pub construction: Construction,
+}
+
+impl Default for CompileOptions {
+    fn default() -> Self {
+        CompileOptions {
+            max_nfa_states: 10_000,
+            max_dfa_states: 10_000,
+            construction: Construction::default(),
+        }
+    }
+}
@@ -0,0 +1,134 @@
+use crate::analysis::char_count;
+use crate::automata::{CodepointTable, DFA};
+
+/// The position of `s` among the strings accepted by `dfa` in shortlex order (shorter strings first, then by
+/// codepoint), or `None` if `s` isn't accepted. Ranks saturate at `u128::MAX`.
+pub fn rank(dfa: &DFA, s: &str) -> Option<u128> {
+    let len = s.chars().count();
+    let counts = dfa.path_counts(len);
+    let table = dfa.codepoint_table();
+
+    let mut rank = counts[..len].iter().fold(0u128, |total, c| total.saturating_add(c[0]));
+    let mut state = 0;
+    for (i, c) in s.chars().enumerate() {
+        let c = c as u32;
+        let after = &counts[len - i - 1];
+        let mut next = None;
+        for (a, b, ns) in &table.0[state] {
+            // Strings continuing with a smaller character come first
+            let smaller = if c > *b { char_count(*a, *b) } else if c > *a { char_count(*a, c - 1) } else { 0 };
+            rank = rank.saturating_add(smaller.saturating_mul(after[*ns]));
+            if (*a..=*b).contains(&c) {
+                next = Some(*ns);
+                break;
+            }
+        }
+        state = next?;
+    }
+
+    dfa.transitions[state].0.then_some(rank)
+}
+
+/// The `n`th string accepted by `dfa` in shortlex order, the inverse of [`rank`]. Panics if the DFA accepts `n`
+/// or fewer strings, or if `n` is `u128::MAX`, where ranks saturate.
+///
+/// This is meant for finite languages. For languages with arbitrarily long strings, strings are counted up to
+/// lengths 16, 32, 64, ... until there are more than `n`, so the time and memory taken grow with the length of the
+/// result.
+pub fn unrank(dfa: &DFA, mut n: u128) -> String {
+    let max_len = dfa.max_len();
+    let mut limit = max_len.unwrap_or(16);
+    loop {
+        let counts = dfa.path_counts(limit);
+        let total = counts.iter().fold(0u128, |total, c| total.saturating_add(c[0]));
+        if n < total {
+            let mut len = 0;
+            while n >= counts[len][0] {
+                n -= counts[len][0];
+                len += 1;
+            }
+            return nth_of_length(&dfa.codepoint_table(), &counts, len, n);
+        }
+
+        assert!(
+            max_len.is_none() && !dfa.is_empty(),
+            "The DFA accepts only {total} strings, so there is no string with rank {n}"
+        );
+        assert!(total < u128::MAX, "Ranks saturate at u128::MAX, so there is no string with rank {n}");
+        limit *= 2;
+    }
+}
+
+impl DFA {
+    /// Every accepted string in shortlex order, optionally only up to `max_len` characters long. Never ends for
+    /// languages with arbitrarily long strings unless `max_len` is given.
+    pub fn strings(&self, max_len: Option<usize>) -> Strings<'_> {
+        let limit = match (max_len, self.is_finite()) {
+            (cap, true) => Some(cap.unwrap_or(usize::MAX).min(self.max_len().unwrap_or(0))),
+            (cap, false) => cap,
+        };
+        Strings { dfa: self, table: self.codepoint_table(), counts: Vec::new(), limit, len: 0, index: 0 }
+    }
+}
+
+/// Iterator returned by [`DFA::strings`]
+pub struct Strings<'a> {
+    dfa: &'a DFA,
+    table: CodepointTable,
+    counts: Vec<Vec<u128>>,
+    /// Longest length to produce
+    limit: Option<usize>,
+    len: usize,
+    /// Index of the next string among those of length `len`
+    index: u128,
+}
+
+impl Iterator for Strings<'_> {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        loop {
+            if self.limit.is_some_and(|limit| self.len > limit) {
+                return None;
+            }
+            if self.counts.len() <= self.len {
+                let grown = (self.len * 2).max(16);
+                self.counts = self.dfa.path_counts(self.limit.map_or(grown, |limit| limit.min(grown)));
+            }
+
+            if self.index < self.counts[self.len][0] {
+                self.index += 1;
+                return Some(nth_of_length(&self.table, &self.counts, self.len, self.index - 1));
+            }
+            self.len += 1;
+            self.index = 0;
+        }
+    }
+}
+
+/// The `n`th string of length `len` in shortlex order, given the [`DFA::path_counts`] up to at least `len`
+pub(crate) fn nth_of_length(table: &CodepointTable, counts: &[Vec<u128>], len: usize, mut n: u128) -> String {
+    let mut s = String::with_capacity(len);
+    let mut state = 0;
+    for remaining in (0..len).rev() {
+        for (a, b, ns) in &table.0[state] {
+            let after = counts[remaining][*ns];
+            let options = char_count(*a, *b).saturating_mul(after);
+            if n >= options {
+                n -= options;
+                continue;
+            }
+
+            // Skip over the surrogates if `a..=b` contains them
+            let mut c = a + (n / after) as u32;
+            if *a < 0xD800 && c >= 0xD800 {
+                c += 0x800;
+            }
+            s.push(char::from_u32(c).unwrap());
+            n %= after;
+            state = *ns;
+            break;
+        }
+    }
+    s
+}
@@ -1,23 +1,59 @@
 use derive_getters::Getters;
-use either::{Either, Left, Right};
-use itertools::Itertools;
 use nom::character::complete::char as cchar;
 use nom::character::complete::{anychar, digit1};
 use nom::combinator::{map_res, opt, recognize};
-use nom::error::Error;
-use std::collections::VecDeque;
+use nom::error::{Error, ErrorKind};
 use nom::IResult;
+use std::fmt::{Display, Formatter};
+use std::ops::Range;
 
 type NResult<'a, T> = IResult<&'a str, T>;
 
-#[derive(Debug, Getters)]
+/// A byte range in the parsed pattern. Nodes that weren't parsed (e.g. built with
+/// [`Pattern`](crate::builder::Pattern)) have an empty span at `0`.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Span {
+        Span { start, end }
+    }
+
+    pub fn to(self, other: Span) -> Span {
+        Span::new(self.start, other.end)
+    }
+}
+
+impl From<Span> for Range<usize> {
+    fn from(s: Span) -> Self {
+        s.start..s.end
+    }
+}
+
+/// Spans don't take part in comparisons - two ASTs are equal if they have the same structure
+#[derive(Debug, Clone, Getters)]
 pub struct ChainedMatchable {
-    matchables: InvertibleMatchable,
-    repetition: Repetition,
-    next: Option<Box<ChainedMatchable>>
+    pub(crate) matchables: InvertibleMatchable,
+    pub(crate) repetition: Repetition,
+    pub(crate) next: Option<Box<ChainedMatchable>>,
+    /// Span of this item and its repetition, excluding `next`
+    pub(crate) span: Span,
+    /// Span of the repetition operator, which is empty at the end of the item if there isn't one
+    pub(crate) repetition_span: Span
 }
 
-#[derive(Debug)]
+impl PartialEq for ChainedMatchable {
+    fn eq(&self, other: &Self) -> bool {
+        self.matchables == other.matchables && self.repetition == other.repetition && self.next == other.next
+    }
+}
+
+impl Eq for ChainedMatchable {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Repetition {
     One,
     Any,
@@ -26,18 +62,39 @@ pub enum Repetition {
     Range(u64, u64)
 }
 
-#[derive(Debug, Getters)]
+#[derive(Debug, Clone, PartialEq, Eq, Getters)]
 pub struct InvertibleMatchable {
-    inverted: bool,
-    matchable: UnionMatchables
+    pub(crate) inverted: bool,
+    pub(crate) matchable: UnionMatchables
 }
 
-#[derive(Debug, Getters)]
+#[derive(Debug, Clone, Getters)]
 pub struct UnionMatchables {
-    matchables: Vec<Matchable>
+    pub(crate) matchables: Vec<Matchable>,
+    /// Span of each of `matchables`
+    pub(crate) spans: Vec<Span>
 }
 
-#[derive(Debug)]
+impl UnionMatchables {
+    pub(crate) fn unspanned(matchables: Vec<Matchable>) -> UnionMatchables {
+        let spans = vec![Span::default(); matchables.len()];
+        UnionMatchables { matchables, spans }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item=(&Matchable, Span)> {
+        self.matchables.iter().zip(self.spans.iter().copied())
+    }
+}
+
+impl PartialEq for UnionMatchables {
+    fn eq(&self, other: &Self) -> bool {
+        self.matchables == other.matchables
+    }
+}
+
+impl Eq for UnionMatchables {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Matchable {
     Char(char),
     // Start,
@@ -60,8 +117,86 @@ impl Matchable {
     }
 }
 
-fn parse_match_group(regex: &str) -> NResult<InvertibleMatchable> {
-    let (r, _) = cchar::<_, Error<_>>('[')(regex).unwrap();
+/// Characters that have to be escaped outside of a `[...]` group
+const SPECIAL_CHARS: &str = "\\.*+?()[]{}|^$";
+/// Characters that have to be escaped inside of a `[...]` group
+const GROUP_SPECIAL_CHARS: &str = "\\[]^-";
+
+/// Iterator over the items of a [`ChainedMatchable`] sequence
+pub struct ChainIter<'a> {
+    current: Option<&'a ChainedMatchable>
+}
+
+impl<'a> Iterator for ChainIter<'a> {
+    type Item = &'a ChainedMatchable;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let c = self.current?;
+        self.current = c.next.as_deref();
+        Some(c)
+    }
+}
+
+impl ChainedMatchable {
+    /// Iterates over this item followed by all items in `next`
+    pub fn iter(&self) -> ChainIter<'_> {
+        ChainIter { current: Some(self) }
+    }
+
+    /// Span of this item and every item after it
+    pub fn full_span(&self) -> Span {
+        self.span.to(self.iter().last().unwrap().span)
+    }
+
+    /// Whether this is a bare `a|b|...` (as produced for the contents of a group) rather than a sequence
+    pub(crate) fn is_alternation(&self) -> bool {
+        let m = &self.matchables;
+        self.repetition == Repetition::One
+            && self.next.is_none()
+            && !m.inverted
+            && m.matchable.matchables.len() > 1
+            && m.matchable.matchables.iter().all(|m| matches!(m, Matchable::Subexpression(_)))
+    }
+}
+
+fn fail<T>(input: &str, kind: ErrorKind) -> NResult<'_, T> {
+    Err(nom::Err::Failure(Error::new(input, kind)))
+}
+
+/// Byte offset of `rest` within `full`, where `rest` is a suffix of `full`
+fn offset(full: &str, rest: &str) -> usize {
+    full.len() - rest.len()
+}
+
+fn span(full: &str, start: &str, end: &str) -> Span {
+    Span::new(offset(full, start), offset(full, end))
+}
+
+/// Only the escapes that [`Display`] writes are supported, so e.g. `\d` is an error rather than a literal `d`
+fn parse_escape(regex: &str) -> NResult<'_, char> {
+    let (r, c) = anychar(regex)?;
+    let c = match c {
+        'n' => '\n',
+        'r' => '\r',
+        't' => '\t',
+        c if SPECIAL_CHARS.contains(c) || GROUP_SPECIAL_CHARS.contains(c) => c,
+        _ => return fail(regex, ErrorKind::Escaped),
+    };
+    Ok((r, c))
+}
+
+fn parse_group_char(regex: &str) -> NResult<'_, char> {
+    let (r, c) = anychar(regex)?;
+    if c == '\\' {
+        parse_escape(r)
+    }
+    else {
+        Ok((r, c))
+    }
+}
+
+fn parse_match_group<'a>(full: &str, regex: &'a str) -> NResult<'a, InvertibleMatchable> {
+    let (r, _) = cchar('[')(regex)?;
 
     let (r, inverted) = if let Ok((r, _)) = cchar::<&str, Error<_>>('^')(r) {
         (r, true)
@@ -69,220 +204,354 @@ fn parse_match_group(regex: &str) -> NResult<InvertibleMatchable> {
         (r, false)
     };
 
-
     let mut rm = r;
-    let mut chars: VecDeque<Either<char, _>> = VecDeque::new();
+    let mut matchables = Vec::new();
+    let mut spans = Vec::new();
     loop {
-        let (r, base_char) = anychar::<_, Error<_>>(rm).unwrap();
-        rm = r;
-
-        match base_char {
-            '\\' => {
-                todo!()
-            }
-            ']' => {
-                break;
-            }
-            '-' => {
-                chars.push_back(Right(()));
-            }
-            c => {
-                chars.push_back(Left(c));
+        if let Ok((r, _)) = cchar::<_, Error<_>>(']')(rm) {
+            if matchables.is_empty() {
+                return fail(rm, ErrorKind::Verify); // Empty group
             }
-        };
-    }
-
-    if chars.is_empty() {
-        panic!()
-    }
+            rm = r;
+            break;
+        }
 
-    let mut ms = VecDeque::from([
-        Matchable::Char(match chars.pop_front().unwrap() {
-            Left(c) => {c}
-            Right(_) => {'-'}
-        })
-    ]);
+        let item_start = rm;
+        let (r, start) = parse_group_char(rm)?;
+        rm = r;
 
-    while chars.len() > 1 {
-        println!("{:?}", chars);
-        if chars[0].is_right() {
-            println!("A");
-            let start = ms.pop_front().unwrap();
-            let start  = if let Matchable::Char(c) = start {c} else {panic!()};
+        // A '-' at the end of the group is literal
+        let is_range = cchar::<_, Error<_>>('-')(rm)
+            .is_ok_and(|(r, _)| cchar::<_, Error<_>>(']')(r).is_err());
 
-            chars.pop_front().unwrap().unwrap_right();
-            let end = chars.pop_front();
-            if let Some(end) = end {
-                println!("B");
-                ms.push_back(Matchable::Range(start, end.unwrap_left()))
-            }
-            else {
-                ms.push_back(Matchable::Char(start));
-                ms.push_back(Matchable::Char('-')); // ended with -
+        if is_range {
+            let (r, end) = parse_group_char(&rm[1..])?;
+            if end < start {
+                return fail(rm, ErrorKind::Verify);
             }
+            rm = r;
+            matchables.push(Matchable::Range(start, end));
         }
         else {
-            println!("C");
-            ms.push_back(Matchable::Char(chars.pop_front().unwrap().unwrap_left()))
+            matchables.push(Matchable::Char(start));
         }
-    }
-
-    if !chars.is_empty() {
-        ms.push_back(Matchable::Char(match chars.pop_front().unwrap() {
-            Left(c) => {c}
-            Right(_) => {'-'}
-        }));
+        spans.push(span(full, item_start, rm));
     }
 
     Ok((rm, InvertibleMatchable {
         inverted,
-        matchable: UnionMatchables {
-            matchables: ms.into_iter().collect_vec()
-        },
+        matchable: UnionMatchables { matchables, spans },
     }))
 }
 
-fn parse_u64(input : &str) -> NResult<u64> {
+fn parse_u64(input : &str) -> NResult<'_, u64> {
     map_res(recognize(digit1), str::parse)(input)
 }
 
-
-pub fn parse_regex(regex: &str, bracket_start: bool) -> NResult<ChainedMatchable> {
-    let r = if bracket_start {
-        let (r, _) = cchar::<_, Error<_>>('(')(regex).unwrap();
-        r
+fn parse_repetition(regex: &str) -> NResult<'_, Repetition> {
+    if let Ok((r, _)) = cchar::<_, Error<_>>('*')(regex) {
+        Ok((r, Repetition::Any))
+    }
+    else if let Ok((r, _)) = cchar::<_, Error<_>>('+')(regex) {
+        Ok((r, Repetition::AtLeast(1)))
+    }
+    else if let Ok((r, _)) = cchar::<_, Error<_>>('?')(regex) {
+        Ok((r, Repetition::LessThanEq(1)))
+    }
+    else if let Ok((r, _)) = cchar::<_, Error<_>>('{')(regex) {
+        let (r, min) = opt(parse_u64)(r)?;
+        let (r, rep) = if let Ok((r, _)) = cchar::<_, Error<_>>(',')(r) {
+            let (r, max) = opt(parse_u64)(r)?;
+            let rep = match (min, max) {
+                (Some(min), Some(max)) if min > max => return fail(regex, ErrorKind::Verify),
+                (Some(min), Some(max)) => Repetition::Range(min, max),
+                (Some(min), None) => Repetition::AtLeast(min),
+                (None, Some(max)) => Repetition::LessThanEq(max),
+                // TODO: This is accepted by https://regex101.com/ and treated as plain text (no repetition)
+                (None, None) => return fail(regex, ErrorKind::Verify),
+            };
+            (r, rep)
+        }
+        else if let Some(n) = min {
+            (r, Repetition::Range(n, n))
+        }
+        else {
+            return fail(regex, ErrorKind::Verify);
+        };
+        let (r, _) = cchar('}')(r)?;
+        Ok((r, rep))
     }
     else {
-        regex
+        Ok((regex, Repetition::One))
+    }
+}
+
+/// Parses a single (possibly repeated) item of a sequence
+fn parse_item<'a>(full: &str, regex: &'a str) -> NResult<'a, ChainedMatchable> {
+    let (r, base_char) = anychar(regex)?;
+
+    let (r, base) = match base_char {
+        '[' => parse_match_group(full, regex)?,
+        '(' => {
+            let (r, c) = parse_alternation(full, regex, true)?;
+            (r, InvertibleMatchable {
+                inverted: false,
+                matchable: UnionMatchables {
+                    matchables: vec![Matchable::Subexpression(c)],
+                    spans: vec![span(full, regex, r)],
+                },
+            })
+        }
+        // Nothing to repeat
+        '*' | '+' | '?' | '{' => return fail(regex, ErrorKind::Verify),
+        // Anchors aren't supported as patterns always match the whole input
+        '^' | '$' => return fail(regex, ErrorKind::Verify),
+        c => {
+            let (r, m) = match c {
+                '\\' => {
+                    let (r, c) = parse_escape(r)?;
+                    (r, Matchable::Char(c))
+                }
+                '.' => (r, Matchable::Any),
+                c => (r, Matchable::Char(c))
+            };
+            (r, InvertibleMatchable {
+                inverted: false,
+                matchable: UnionMatchables { matchables: vec![m], spans: vec![span(full, regex, r)] },
+            })
+        }
     };
 
+    let repetition_start = r;
+    let (r, repetition) = parse_repetition(r)?;
 
-    let mut ors = Vec::new();
+    Ok((r, ChainedMatchable {
+        matchables: base,
+        repetition,
+        next: None,
+        span: span(full, regex, r),
+        repetition_span: span(full, repetition_start, r),
+    }))
+}
 
-    let mut rm = r;
-    loop {
-        let (r, base_char) = anychar::<_, Error<_>>(rm).unwrap();
+/// Parses items up to the next `|`, `)` or the end of the input
+fn parse_sequence<'a>(full: &str, regex: &'a str) -> NResult<'a, ChainedMatchable> {
+    let mut items = Vec::new();
+    let mut rm = regex;
+    while !rm.is_empty() && !rm.starts_with(['|', ')']) {
+        let (r, item) = parse_item(full, rm)?;
+        rm = r;
+        items.push(item);
+    }
 
-        let base = match base_char {
-            '\\' => {
-                // rm = r;
-                todo!()
-            }
-            '[' => {
-                let (r, i) = parse_match_group(rm).unwrap();
-                rm = r;
-                i
-            }
-            '(' => {
-                let (r, c) = parse_regex(rm, true).unwrap();
-                rm = r;
-                InvertibleMatchable {
-                    inverted: false,
-                    matchable: UnionMatchables {
-                        matchables: vec![Matchable::Subexpression(c)],
-                    },
-                }
-            }
-            '{' => {
-                panic!()
-            }
-            '}' => {
-                panic!()
-            }
-            c => {
-                rm = r;
-                InvertibleMatchable {
-                    inverted: false,
-                    matchable: UnionMatchables {
-                        matchables: vec![match c {
-                            // '^' => Matchable::Start,
-                            // '$' => Matchable::End,
-                            '^' => panic!(),
-                            '$' => panic!(),
-                            '.' => Matchable::Any,
-                            c => Matchable::Char(c)
-                        }],
-                    },
-                }
-            }
-        };
+    let Some(mut m) = items.pop() else {
+        return fail(regex, ErrorKind::Verify); // Empty alternatives / groups aren't supported
+    };
+    while let Some(mut prev) = items.pop() {
+        prev.next = Some(Box::new(m));
+        m = prev;
+    }
+    Ok((rm, m))
+}
 
-        let repetition = if let Ok((r, _)) = cchar::<_, Error<_>>('*')(rm) {
-            rm = r;
-            Repetition::Any
-        } else if let Ok((r, _)) = cchar::<_, Error<_>>('{')(rm) {
-            rm = r;
-            let (r, min) = opt(parse_u64)(rm)?;
-            rm = r;
-            rm = cchar::<_, Error<_>>(',')(rm)?.0;
-            let (r, max) = opt(parse_u64)(rm)?;
-            rm = r;
-            let rep = if min.is_some() && max.is_some() {
-                Repetition::Range(min.unwrap(), max.unwrap())
-            }
-            else if min.is_some() {
-                Repetition::AtLeast(min.unwrap())
-            }
-            else if max.is_some() {
-                Repetition::LessThanEq(max.unwrap())
-            }
-            else {
-                panic!() // TODO: This is accepted by https://regex101.com/ and treated as plain text (no repetition)
-            };
-            rm = cchar::<_, Error<_>>('}')(rm).unwrap().0;
-            rep
-        }
-        else if let Ok((r, _)) = cchar::<_, Error<_>>('?')(rm) {
-            rm = r;
-            Repetition::LessThanEq(1)
-        }
-        else if let Ok((r, _)) = cchar::<_, Error<_>>('+')(rm) {
-            rm = r;
-            Repetition::AtLeast(1)
-        }
-        else {
-            Repetition::One
-        };
+fn parse_alternation<'a>(full: &str, regex: &'a str, bracket_start: bool) -> NResult<'a, ChainedMatchable> {
+    let mut rm = if bracket_start {
+        cchar('(')(regex)?.0
+    }
+    else {
+        regex
+    };
+    let start = rm;
 
-        ors.push(ChainedMatchable {
-            matchables: base,
-            repetition,
-            next: None,
-        });
+    let mut ors = Vec::new();
+    let mut spans = Vec::new();
+    loop {
+        let (r, c) = parse_sequence(full, rm)?;
+        spans.push(span(full, rm, r));
+        rm = r;
+        ors.push(c);
 
-        if let Ok((r, _)) = cchar::<_, Error<_>>('|')(r) {
+        if let Ok((r, _)) = cchar::<_, Error<_>>('|')(rm) {
             rm = r;
             continue;
         }
         break;
     }
-    let r = rm;
+    let end = rm;
+
+    if bracket_start {
+        rm = cchar(')')(rm)?.0;
+    }
 
-    debug_assert!(!ors.is_empty());
-    let mut m = if ors.len() == 1 {
-        ors.into_iter().next().unwrap()
+    let m = if ors.len() == 1 {
+        ors.pop().unwrap()
     }
     else {
         ChainedMatchable {
             matchables: InvertibleMatchable {
                 inverted: false,
                 matchable: UnionMatchables {
-                    matchables: ors.into_iter().map(|o| {
-                        Matchable::Subexpression(o)
-                    }).collect_vec(),
+                    matchables: ors.into_iter().map(Matchable::Subexpression).collect(),
+                    spans,
                 }
             },
             repetition: Repetition::One,
             next: None,
+            span: span(full, start, end),
+            repetition_span: span(full, end, end),
         }
     };
 
-    if let Ok((r, _)) = cchar::<_, Error<_>>(')')(r) {
-        return Ok((r, m))
+    Ok((rm, m))
+}
+
+/// Parses a (possibly alternated) regex. If `bracket_start` is set, the regex must be enclosed in `(...)`,
+/// otherwise parsing stops at the end of the input or an unmatched `)`.
+///
+/// Spans are relative to the start of `regex`.
+pub fn parse_regex(regex: &str, bracket_start: bool) -> NResult<'_, ChainedMatchable> {
+    parse_alternation(regex, regex, bracket_start)
+}
+
+fn write_escaped(f: &mut Formatter<'_>, c: char, special: &str) -> std::fmt::Result {
+    match c {
+        '\n' => write!(f, "\\n"),
+        '\r' => write!(f, "\\r"),
+        '\t' => write!(f, "\\t"),
+        c if special.contains(c) => write!(f, "\\{c}"),
+        c => write!(f, "{c}")
+    }
+}
+
+/// Writes characters, ranges and `.` (as the range of every character) as a `[...]` group
+fn write_group(f: &mut Formatter<'_>, ms: &[Matchable], inverted: bool) -> std::fmt::Result {
+    write!(f, "[{}", if inverted { "^" } else { "" })?;
+    for m in ms {
+        let (a, b) = match m {
+            Matchable::Char(c) => {
+                write_escaped(f, *c, GROUP_SPECIAL_CHARS)?;
+                continue;
+            }
+            Matchable::Range(a, b) => (*a, *b),
+            Matchable::Any => ('\0', char::MAX),
+            Matchable::Subexpression(_) => unreachable!("Groups can't contain subexpressions"),
+        };
+        write_escaped(f, a, GROUP_SPECIAL_CHARS)?;
+        write!(f, "-")?;
+        write_escaped(f, b, GROUP_SPECIAL_CHARS)?;
     }
+    write!(f, "]")
+}
 
-    let (r, c) = parse_regex(r, false).unwrap();
-    m.next = Some(Box::new(c));
-    Ok((r, m))
+/// Writes an alternation branch, bracketing it if it would otherwise merge with the surrounding alternation
+fn write_branch(f: &mut Formatter<'_>, c: &ChainedMatchable) -> std::fmt::Result {
+    if c.is_alternation() {
+        write!(f, "({c})")
+    }
+    else {
+        write!(f, "{c}")
+    }
+}
+
+impl Display for ChainedMatchable {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.is_alternation() {
+            for (i, m) in self.matchables.matchable.matchables.iter().enumerate() {
+                if i != 0 {
+                    write!(f, "|")?;
+                }
+                let Matchable::Subexpression(c) = m else { unreachable!() };
+                write_branch(f, c)?;
+            }
+            return Ok(());
+        }
+
+        for c in self.iter() {
+            write!(f, "{}{}", c.matchables, c.repetition)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for Repetition {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Repetition::One => Ok(()),
+            Repetition::Any => write!(f, "*"),
+            Repetition::AtLeast(1) => write!(f, "+"),
+            Repetition::AtLeast(x) => write!(f, "{{{x},}}"),
+            Repetition::LessThanEq(1) => write!(f, "?"),
+            Repetition::LessThanEq(x) => write!(f, "{{,{x}}}"),
+            Repetition::Range(x, y) if x == y => write!(f, "{{{x}}}"),
+            Repetition::Range(x, y) => write!(f, "{{{x},{y}}}"),
+        }
+    }
 }
 
+/// Written as a single item that a [`Repetition`] can follow. An empty union matches nothing, so it's written as
+/// the inverted group of every character.
+impl Display for InvertibleMatchable {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let ms = &self.matchable.matchables;
+        if ms.is_empty() {
+            return if self.inverted { write!(f, ".") } else { write_group(f, &[Matchable::Any], true) };
+        }
+        let is_group = ms.iter().all(|m| matches!(m, Matchable::Char(_) | Matchable::Range(_, _)));
+
+        if self.inverted || (is_group && (ms.len() > 1 || matches!(ms[..], [Matchable::Range(_, _)]))) {
+            return write_group(f, ms, self.inverted);
+        }
+
+        if let [m] = &ms[..] {
+            write!(f, "{m}")
+        }
+        else {
+            write!(f, "({})", self.matchable)
+        }
+    }
+}
+
+/// Consecutive characters and ranges are written as a single `[...]` branch
+impl Display for UnionMatchables {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let is_char = |m: &Matchable| matches!(m, Matchable::Char(_) | Matchable::Range(_, _));
+        let mut rest = &self.matchables[..];
+        let mut first = true;
+        while let Some(m) = rest.first() {
+            if !first {
+                write!(f, "|")?;
+            }
+            first = false;
+
+            let chars = rest.iter().take_while(|m| is_char(m)).count();
+            if chars > 1 {
+                write_group(f, &rest[..chars], false)?;
+                rest = &rest[chars..];
+                continue;
+            }
+            match m {
+                Matchable::Subexpression(c) => write_branch(f, c)?,
+                m => write!(f, "{m}")?
+            }
+            rest = &rest[1..];
+        }
+        Ok(())
+    }
+}
+
+impl Display for Matchable {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Matchable::Char(c) => write_escaped(f, *c, SPECIAL_CHARS),
+            Matchable::Range(a, b) => {
+                write!(f, "[")?;
+                write_escaped(f, *a, GROUP_SPECIAL_CHARS)?;
+                write!(f, "-")?;
+                write_escaped(f, *b, GROUP_SPECIAL_CHARS)?;
+                write!(f, "]")
+            }
+            Matchable::Any => write!(f, "."),
+            Matchable::Subexpression(c) => write!(f, "({c})"),
+        }
+    }
+}
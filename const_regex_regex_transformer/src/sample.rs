@@ -0,0 +1,86 @@
+use crate::automata::{CodepointTable, DFA};
+use crate::rank::nth_of_length;
+use std::ops::{Bound, RangeBounds};
+
+/// Samples strings uniformly from those accepted by a DFA with lengths (in characters) in a given range. Counting
+/// saturates at `u128::MAX` strings, beyond which sampling is only approximately uniform.
+///
+/// ```
+/// # use const_regex_regex_transformer::{sample::Sampler, to_automata, to_regex};
+/// let sampler = Sampler::new(to_automata(to_regex("[a-z]+@[a-z]+\\.com")), 5..=20);
+/// let mut seed = 1u64;
+/// let s = sampler.sample(|| { seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1); seed }).unwrap();
+/// assert!(s.ends_with(".com"));
+/// ```
+#[derive(Debug, Clone)]
+pub struct Sampler {
+    table: CodepointTable,
+    min_len: usize,
+    counts: Vec<Vec<u128>>,
+    len: u128,
+}
+
+impl Sampler {
+    /// Panics if `lengths` has no upper bound
+    pub fn new(dfa: DFA, lengths: impl RangeBounds<usize>) -> Sampler {
+        let min_len = match lengths.start_bound() {
+            Bound::Included(x) => *x,
+            Bound::Excluded(x) => x + 1,
+            Bound::Unbounded => 0,
+        };
+        let max_len = match lengths.end_bound() {
+            Bound::Included(x) => *x,
+            Bound::Excluded(x) => x.checked_sub(1).expect("Empty length range"),
+            Bound::Unbounded => panic!("Sampling needs a maximum length"),
+        };
+
+        let counts = dfa.path_counts(max_len);
+        let len = counts.get(min_len..).unwrap_or_default().iter().fold(0u128, |total, c| total.saturating_add(c[0]));
+        Sampler { table: dfa.codepoint_table(), min_len, counts, len }
+    }
+
+    /// The number of strings that can be sampled
+    pub fn len(&self) -> u128 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The `n`th string that can be sampled in shortlex order, or `None` if `n >= self.len()`
+    pub fn get(&self, mut n: u128) -> Option<String> {
+        let mut len = self.min_len;
+        while n >= *self.counts.get(len)?.first()? {
+            n -= self.counts[len][0];
+            len += 1;
+        }
+
+        Some(nth_of_length(&self.table, &self.counts, len, n))
+    }
+
+    /// A uniformly random string, or `None` if there are none. `random` must return uniformly random `u64`s.
+    pub fn sample(&self, mut random: impl FnMut() -> u64) -> Option<String> {
+        if self.is_empty() {
+            return None;
+        }
+
+        // Rejection sampling to avoid bias from `%`
+        let limit = u128::MAX - u128::MAX % self.len;
+        loop {
+            let x = ((random() as u128) << 64) | random() as u128;
+            if x < limit {
+                return self.get(x % self.len);
+            }
+        }
+    }
+
+    /// A proptest strategy generating the strings this samples from. Panics if there are none.
+    #[cfg(feature = "proptest")]
+    pub fn strategy(self) -> impl proptest::strategy::Strategy<Value=String> {
+        use proptest::strategy::Strategy;
+
+        assert!(!self.is_empty(), "No strings to sample");
+        (0..self.len).prop_map(move |n| self.get(n).unwrap())
+    }
+}
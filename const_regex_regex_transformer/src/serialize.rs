@@ -0,0 +1,184 @@
+//! A stable, versioned serialisation of [`DFA`]s, so that expensive automata can be built once and loaded with
+//! `include_dfa!`. All integers are little-endian:
+//!
+//! | Field       | Size                | Contents                                                              |
+//! |-------------|---------------------|-----------------------------------------------------------------------|
+//! | Magic       | 4                   | `CRDF`                                                                |
+//! | Version     | 2                   | [`FORMAT_VERSION`]                                                    |
+//! | States      | 4                   | Number of states `s`, the first of which is the start                 |
+//! | Classes     | 4                   | Number of character classes `c`                                       |
+//! | Partition   | 4 × `c`             | First codepoint of each class, starting at `0` and increasing         |
+//! | State table | `s` × (1 + 4 × `c`) | For each state, `1` if it accepts, then its target for each class     |
+//! |             |                     | (`u32::MAX` if there is none)                                         |
+//! | Checksum    | 8                   | 64 bit FNV-1a hash of everything before it                            |
+//!
+//! With the `json` feature, the same data can also be written as JSON (with the checksum of the binary form).
+
+use crate::automata::{codepoint_transition, merge_adjacent, DFA};
+use crate::error::DecodeError;
+
+pub const FORMAT_VERSION: u16 = 1;
+const MAGIC: &[u8; 4] = b"CRDF";
+const NO_TARGET: u32 = u32::MAX;
+
+#[cfg_attr(feature = "json", derive(serde::Serialize, serde::Deserialize))]
+struct Encoded {
+    version: u16,
+    partition: Vec<u32>,
+    accepting: Vec<bool>,
+    transitions: Vec<Vec<Option<u32>>>,
+    checksum: u64,
+}
+
+impl Encoded {
+    /// Everything in the binary format other than the checksum
+    fn body(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(self.version.to_le_bytes());
+        bytes.extend((self.accepting.len() as u32).to_le_bytes());
+        bytes.extend((self.partition.len() as u32).to_le_bytes());
+        for start in &self.partition {
+            bytes.extend(start.to_le_bytes());
+        }
+        for (accepting, targets) in self.accepting.iter().zip(&self.transitions) {
+            bytes.push(*accepting as u8);
+            for target in targets {
+                bytes.extend(target.unwrap_or(NO_TARGET).to_le_bytes());
+            }
+        }
+        bytes
+    }
+
+    fn into_dfa(self) -> Result<DFA, DecodeError> {
+        if self.version != FORMAT_VERSION {
+            return Err(DecodeError::UnsupportedVersion(self.version));
+        }
+        if fnv1a(&self.body()) != self.checksum {
+            return Err(DecodeError::ChecksumMismatch);
+        }
+
+        let states = self.accepting.len();
+        if states == 0 {
+            return Err(DecodeError::Invalid("no states"));
+        }
+        if self.partition.first() != Some(&0)
+            || self.partition.windows(2).any(|w| w[0] >= w[1])
+            || self.partition.last().is_some_and(|c| *c > char::MAX as u32) {
+            return Err(DecodeError::Invalid("bad alphabet partition"));
+        }
+        if self.transitions.len() != states
+            || self.transitions.iter().flatten().flatten().any(|t| *t as usize >= states)
+            || self.transitions.iter().any(|ts| ts.len() != self.partition.len()) {
+            return Err(DecodeError::Invalid("bad state table"));
+        }
+
+        let ends = self.partition.iter().skip(1).map(|c| c - 1).chain([char::MAX as u32]);
+        let classes = self.partition.iter().copied().zip(ends).collect::<Vec<_>>();
+        let transitions = self.accepting.into_iter().zip(self.transitions).map(|(accepting, targets)| {
+            let ranges = classes.iter().zip(targets)
+                .filter_map(|((a, b), target)| Some((*a, *b, target? as usize)));
+            let ts = merge_adjacent(ranges).into_iter()
+                .filter_map(|(a, b, ns)| Some((codepoint_transition(a, b)?, ns)))
+                .collect();
+            (accepting, ts)
+        }).collect();
+        Ok(DFA { transitions })
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl Reader<'_> {
+    fn take(&mut self, n: usize) -> Result<&[u8], DecodeError> {
+        let taken = self.bytes.get(self.pos..self.pos + n).ok_or(DecodeError::Truncated)?;
+        self.pos += n;
+        Ok(taken)
+    }
+
+    fn u32(&mut self) -> Result<u32, DecodeError> {
+        self.take(4).map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+    }
+}
+
+/// 64 bit FNV-1a
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, b| (hash ^ *b as u64).wrapping_mul(0x100000001b3))
+}
+
+impl DFA {
+    fn encode(&self) -> Encoded {
+        let table = self.codepoint_table();
+
+        // Split the alphabet wherever any transition starts or ends
+        let mut partition = vec![0];
+        partition.extend(table.0.iter().flatten().flat_map(|(a, b, _)| [*a, b + 1]));
+        partition.retain(|c| *c <= char::MAX as u32);
+        partition.sort_unstable();
+        partition.dedup();
+
+        let transitions = (0..self.transitions.len())
+            .map(|s| partition.iter().map(|c| table.step(s, *c).map(|ns| ns as u32)).collect())
+            .collect();
+        let mut encoded = Encoded {
+            version: FORMAT_VERSION,
+            partition,
+            accepting: self.transitions.iter().map(|(s, _)| *s).collect(),
+            transitions,
+            checksum: 0,
+        };
+        encoded.checksum = fnv1a(&encoded.body());
+        encoded
+    }
+
+    /// The DFA in the binary format described in [`crate::serialize`]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let encoded = self.encode();
+        let mut bytes = encoded.body();
+        bytes.extend(encoded.checksum.to_le_bytes());
+        bytes
+    }
+
+    /// Reads a DFA written by [`DFA::to_bytes`]
+    pub fn from_bytes(bytes: &[u8]) -> Result<DFA, DecodeError> {
+        let mut r = Reader { bytes, pos: 0 };
+        if r.take(4)? != MAGIC {
+            return Err(DecodeError::BadMagic);
+        }
+        let version = u16::from_le_bytes(r.take(2)?.try_into().unwrap());
+        if version != FORMAT_VERSION {
+            return Err(DecodeError::UnsupportedVersion(version));
+        }
+
+        let (states, classes) = (r.u32()? as usize, r.u32()? as usize);
+        let partition = (0..classes).map(|_| r.u32()).collect::<Result<Vec<_>, _>>()?;
+        let mut accepting = Vec::new();
+        let mut transitions = Vec::new();
+        for _ in 0..states {
+            accepting.push(r.take(1)?[0] != 0);
+            let targets = (0..classes).map(|_| r.u32().map(|t| (t != NO_TARGET).then_some(t)));
+            transitions.push(targets.collect::<Result<_, _>>()?);
+        }
+        let checksum = u64::from_le_bytes(r.take(8)?.try_into().unwrap());
+        if r.pos != bytes.len() {
+            return Err(DecodeError::Invalid("trailing data"));
+        }
+
+        Encoded { version, partition, accepting, transitions, checksum }.into_dfa()
+    }
+
+    /// The DFA as JSON, with the same contents as [`DFA::to_bytes`]
+    #[cfg(feature = "json")]
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(&self.encode()).unwrap()
+    }
+
+    /// Reads a DFA written by [`DFA::to_json`]
+    #[cfg(feature = "json")]
+    pub fn from_json(json: &str) -> Result<DFA, DecodeError> {
+        let encoded: Encoded = serde_json::from_str(json).map_err(|e| DecodeError::Json(e.to_string()))?;
+        encoded.into_dfa()
+    }
+}
@@ -0,0 +1,223 @@
+use crate::automata::{subset_construction, try_to_nfa, walk, NFAState, DFA, NFA};
+use crate::error::CompileError;
+use crate::options::CompileOptions;
+use crate::regex::ChainedMatchable;
+use crate::simplify::simplify;
+use const_regex_util::char_to_utf8;
+use proc_macro2::TokenStream;
+use quote::{quote, ToTokens, TokenStreamExt};
+
+/// A set of pattern IDs
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct PatternSet(Vec<u64>);
+
+impl PatternSet {
+    pub fn insert(&mut self, id: usize) {
+        if self.0.len() <= id / 64 {
+            self.0.resize(id / 64 + 1, 0);
+        }
+        self.0[id / 64] |= 1 << (id % 64);
+    }
+
+    pub fn contains(&self, id: usize) -> bool {
+        self.0.get(id / 64).is_some_and(|w| w & (1 << (id % 64)) != 0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.iter().all(|w| *w == 0)
+    }
+
+    /// The IDs in increasing order
+    pub fn iter(&self) -> impl Iterator<Item=usize> + '_ {
+        (0..self.0.len() * 64).filter(|id| self.contains(*id))
+    }
+}
+
+/// A DFA matching several regexes at once, whose states also record which of the regexes match when input ends
+/// there. Its states accept if any regex matches.
+#[derive(Debug, Clone)]
+pub struct RegexSet {
+    pub dfa: DFA,
+    /// The regexes matched in each state of `dfa`
+    pub matches: Vec<PatternSet>,
+    /// The number of regexes
+    pub len: usize,
+}
+
+impl RegexSet {
+    /// The regexes matching `s`
+    pub fn matches(&self, s: &str) -> PatternSet {
+        self.dfa.run(s).map(|state| self.matches[state].clone()).unwrap_or_default()
+    }
+
+    /// The first regex matching `s`
+    pub fn first_match(&self, s: &str) -> Option<usize> {
+        self.matches(s).iter().next()
+    }
+
+    /// The first regex matching the longest prefix of `s` that any regex matches, and the prefix's length in bytes
+    pub fn longest_match(&self, s: &str) -> Option<(usize, usize)> {
+        let first = |state: usize, len| self.matches[state].iter().next().map(|id| (id, len));
+        let mut state = 0;
+        let mut longest = first(0, 0);
+        for (i, c) in s.char_indices() {
+            let Some((_, ns)) = self.dfa.transitions[state].1.iter().find(|(t, _)| t.matches(char_to_utf8(c))) else {
+                break;
+            };
+            state = *ns;
+            longest = first(state, i + c.len_utf8()).or(longest);
+        }
+        longest
+    }
+
+    /// The first regex matching each state, as an array of `Option<usize>`
+    fn first_tokens(&self) -> TokenStream {
+        let first = self.matches.iter().map(|ids| match ids.iter().next() {
+            Some(id) => quote! { Some(#id) },
+            None => quote! { None },
+        });
+        quote! { [#(#first),*] }
+    }
+
+    /// A `const fn longest_match(input: &str, start: usize) -> Option<(usize, usize)>` finding the first regex
+    /// matching the longest prefix of `input[start..]` that any regex matches, and where that prefix ends
+    pub fn longest_match_fn(&self) -> TokenStream {
+        let transitions = self.dfa.transition_consts("");
+        let states = self.matches.len();
+        let first = self.first_tokens();
+
+        quote! {
+            const fn longest_match(input: &str, start: usize) -> Option<(usize, usize)> {
+                #transitions
+                const FIRST: [Option<usize>; #states] = #first;
+
+                let mut state = 0;
+                let mut string_pos = start;
+                let mut longest = match FIRST[0] {
+                    Some(id) => Some((id, start)),
+                    None => None,
+                };
+                'outer: loop {
+                    if string_pos == input.as_bytes().len() { return longest; }
+                    let (c, d) = const_regex_util::next_char(input, string_pos);
+                    string_pos = d;
+                    let ts = TRANSITIONS[state].1;
+                    let mut i = 0;
+                    while i < ts.len() {
+                        let (t, ns) = &ts[i];
+                        if t.matches(c) {
+                            state = *ns;
+                            if let Some(id) = FIRST[state] {
+                                longest = Some((id, string_pos));
+                            }
+                            continue 'outer;
+                        }
+                        i += 1;
+                    }
+                    return longest;
+                }
+            }
+        }
+    }
+}
+
+/// Builds a [`RegexSet`] from `regexes` by subset construction on the union of their NFAs. As this needs NFAs,
+/// [`Construction::Brzozowski`](crate::options::Construction::Brzozowski) falls back to Thompson's construction.
+pub fn try_to_set(regexes: impl IntoIterator<Item=ChainedMatchable>, options: &CompileOptions) -> Result<RegexSet, CompileError> {
+    let nfas = regexes.into_iter()
+        .map(|regex| try_to_nfa(simplify(regex), options))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // A new start state with epsilon transitions to each NFA, followed by the NFAs, followed by a state for each
+    // NFA's success, which leads to the overall success state
+    let size = 1 + nfas.iter().map(NFA::state_count).sum::<usize>();
+    let required = (size + nfas.len()) as u64 + 1;
+    if required > options.max_nfa_states {
+        return Err(CompileError::TooManyNfaStates { limit: options.max_nfa_states, required });
+    }
+
+    let mut states = vec![NFAState::default()];
+    for (id, nfa) in nfas.iter().enumerate() {
+        let offset = states.len();
+        let success = size + id;
+        states[0].add_transition(None, offset);
+        states.extend(nfa.states.iter().map(|s| NFAState {
+            transitions: s.transitions.iter()
+                .map(|(t, ns)| (*t, if *ns == nfa.states.len() { success } else { offset + ns }))
+                .collect(),
+        }));
+    }
+    for _ in 0..nfas.len() {
+        let mut state = NFAState::default();
+        state.add_transition(None, size + nfas.len());
+        states.push(state);
+    }
+
+    let (dfa, sets) = subset_construction(&NFA { states }, options)?;
+    let matches = sets.iter().map(|set| {
+        let mut ids = PatternSet::default();
+        for s in set.iter().filter(|s| (size..size + nfas.len()).contains(*s)) {
+            ids.insert(s - size);
+        }
+        ids
+    }).collect();
+    Ok(RegexSet { dfa, matches, len: nfas.len() })
+}
+
+impl ToTokens for RegexSet {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        let len = self.len;
+        let states = self.matches.len();
+        let matches = self.matches.iter().map(|ids| {
+            let flags = (0..len).map(|id| ids.contains(id));
+            quote! { [#(#flags),*] }
+        });
+        let first = self.first_tokens();
+
+        let transitions = self.dfa.transition_consts("");
+        let walk = walk(quote! { Some(state) }, quote! { None }, quote! {});
+
+        tokens.append_all(quote! {
+            {
+                struct RegexSet;
+
+                impl RegexSet {
+                    /// The number of regexes in the set
+                    pub const LEN: usize = #len;
+                    const MATCHES: [[bool; #len]; #states] = [#(#matches),*];
+                    const FIRST: [Option<usize>; #states] = #first;
+
+                    /// The state reached after reading `input`, or `None` if no regex can match
+                    const fn state(input: &str) -> Option<usize> {
+                        #transitions
+
+                        #walk
+                    }
+
+                    /// Whether each regex matches `input`
+                    pub const fn matches(&self, input: &str) -> [bool; #len] {
+                        match Self::state(input) {
+                            Some(state) => Self::MATCHES[state],
+                            None => [false; #len],
+                        }
+                    }
+
+                    /// The first regex matching `input`
+                    pub const fn first_match(&self, input: &str) -> Option<usize> {
+                        match Self::state(input) {
+                            Some(state) => Self::FIRST[state],
+                            None => None,
+                        }
+                    }
+
+                    /// Whether any regex matches `input`
+                    pub const fn test(&self, input: &str) -> bool {
+                        self.first_match(input).is_some()
+                    }
+                }
+
+                RegexSet {}
+            }
+        });
+    }
+}
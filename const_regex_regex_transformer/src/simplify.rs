@@ -0,0 +1,249 @@
+use crate::automata::{bounds_repetition, repetition_bounds};
+use crate::regex::{ChainedMatchable, InvertibleMatchable, Matchable, Repetition, Span, UnionMatchables};
+
+/// Rewrites a regex into an equivalent, smaller one to reduce the size of the NFA built from it:
+/// - adjacent characters / ranges in a union are merged (`[abcx]` → `[a-cx]`, `a|c|b` → `[a-c]`)
+/// - common prefixes are factored out of alternations (`abc|abd` → `ab[c-d]`)
+/// - subexpressions that don't need to be one are flattened (`a(bc)` → `abc`, `(a)*` → `a*`)
+/// - redundant repetitions are folded (`x{1}` → `x`, `x{0,}` → `x*`, `xx*` → `x+`)
+///
+/// Rewritten nodes lose their spans.
+pub fn simplify(regex: ChainedMatchable) -> ChainedMatchable {
+    simplify_sequence(regex)
+}
+
+fn item(matchables: Vec<(Matchable, Span)>, inverted: bool, repetition: Repetition) -> ChainedMatchable {
+    let (matchables, spans) = matchables.into_iter().unzip();
+    ChainedMatchable {
+        matchables: InvertibleMatchable {
+            inverted,
+            matchable: UnionMatchables { matchables, spans },
+        },
+        repetition,
+        next: None,
+        span: Span::default(),
+        repetition_span: Span::default(),
+    }
+}
+
+fn split_items(sequence: ChainedMatchable) -> Vec<ChainedMatchable> {
+    let mut items = Vec::new();
+    let mut current = Some(sequence);
+    while let Some(mut c) = current {
+        current = c.next.take().map(|n| *n);
+        items.push(c);
+    }
+    items
+}
+
+fn join_items(items: Vec<ChainedMatchable>) -> ChainedMatchable {
+    let mut items = items.into_iter().rev();
+    let mut m = items.next().expect("Sequences can't be empty");
+    for mut prev in items {
+        prev.next = Some(Box::new(m));
+        m = prev;
+    }
+    m
+}
+
+fn fold_repetition(repetition: Repetition) -> Repetition {
+    match repetition {
+        Repetition::AtLeast(0) => Repetition::Any,
+        Repetition::Range(1, 1) => Repetition::One,
+        Repetition::Range(0, x) => Repetition::LessThanEq(x),
+        r => r
+    }
+}
+
+fn simplify_sequence(sequence: ChainedMatchable) -> ChainedMatchable {
+    let mut items = Vec::new();
+    for mut item in split_items(sequence) {
+        item.matchables = simplify_union(item.matchables);
+        item.repetition = fold_repetition(item.repetition);
+
+        let ms = &item.matchables.matchable.matchables;
+        if item.matchables.inverted || ms.len() != 1 {
+            items.push(item);
+            continue;
+        }
+        let Matchable::Subexpression(inner) = &ms[0] else {
+            items.push(item);
+            continue;
+        };
+
+        if item.repetition == Repetition::One {
+            // a(bc)d → abcd
+            let Some(Matchable::Subexpression(inner)) = item.matchables.matchable.matchables.pop() else { unreachable!() };
+            items.extend(split_items(inner));
+        }
+        else if inner.next.is_none() && inner.repetition == Repetition::One {
+            // (a)* → a*
+            let Some(Matchable::Subexpression(inner)) = item.matchables.matchable.matchables.pop() else { unreachable!() };
+            item.matchables = inner.matchables;
+            items.push(item);
+        }
+        else {
+            items.push(item);
+        }
+    }
+    join_items(merge_repeats(items))
+}
+
+/// Merges adjacent repetitions of the same thing by adding their bounds (`xx*` → `x+`, `x{2}x?` → `x{2,3}`)
+fn merge_repeats(items: Vec<ChainedMatchable>) -> Vec<ChainedMatchable> {
+    let mut merged: Vec<ChainedMatchable> = Vec::new();
+    for item in items {
+        if let Some(last) = merged.last_mut().filter(|last| last.matchables == item.matchables) {
+            let ((min_a, max_a), (min_b, max_b)) = (repetition_bounds(&last.repetition), repetition_bounds(&item.repetition));
+            let min = min_a.checked_add(min_b);
+            let max = match (max_a, max_b) {
+                (Some(a), Some(b)) => a.checked_add(b).map(Some),
+                _ => Some(None),
+            };
+            if let (Some(min), Some(max)) = (min, max) {
+                last.repetition = fold_repetition(bounds_repetition(min, max));
+                last.span = Span::default();
+                last.repetition_span = Span::default();
+                continue;
+            }
+        }
+        merged.push(item);
+    }
+    merged
+}
+
+fn simplify_union(matchable: InvertibleMatchable) -> InvertibleMatchable {
+    let inverted = matchable.inverted;
+    let mut elements = Vec::new();
+    for (m, span) in matchable.matchable.matchables.into_iter().zip(matchable.matchable.spans) {
+        let Matchable::Subexpression(c) = m else {
+            elements.push((m, span));
+            continue;
+        };
+
+        let c = simplify_sequence(c);
+        if !inverted && !c.matchables.inverted && c.next.is_none() && c.repetition == Repetition::One {
+            // a|(b|c) → a|b|c
+            let u = c.matchables.matchable;
+            elements.extend(u.matchables.into_iter().zip(u.spans));
+        }
+        else {
+            elements.push((Matchable::Subexpression(c), span));
+        }
+    }
+
+    if !inverted {
+        elements = factor_prefixes(elements);
+    }
+
+    InvertibleMatchable {
+        inverted,
+        matchable: merge_ranges(elements),
+    }
+}
+
+/// Alternation elements sharing a first item, each as (rest of the sequence, original element, span)
+type PrefixGroup = (ChainedMatchable, Vec<(Option<ChainedMatchable>, Matchable, Span)>);
+
+/// `abc|abd` → `ab(c|d)`, `ab|abc` → `ab(c)?`
+fn factor_prefixes(elements: Vec<(Matchable, Span)>) -> Vec<(Matchable, Span)> {
+    let mut groups: Vec<PrefixGroup> = Vec::new();
+    for (m, span) in elements {
+        let (mut head, original) = match m {
+            Matchable::Subexpression(c) => (c.clone(), Matchable::Subexpression(c)),
+            m => (item(vec![(m.clone(), span)], false, Repetition::One), m)
+        };
+        let tail = head.next.take().map(|n| *n);
+
+        if let Some((_, members)) = groups.iter_mut().find(|(h, _)| *h == head) {
+            members.push((tail, original, span));
+        }
+        else {
+            groups.push((head, vec![(tail, original, span)]));
+        }
+    }
+
+    let mut elements = Vec::new();
+    for (head, mut members) in groups {
+        if members.len() == 1 {
+            let (_, m, span) = members.pop().unwrap();
+            elements.push((m, span));
+            continue;
+        }
+
+        let has_empty = members.iter().any(|(t, _, _)| t.is_none());
+        let tails = members.into_iter().filter_map(|(t, _, _)| t).collect::<Vec<_>>();
+
+        let mut sequence = vec![head];
+        if !tails.is_empty() {
+            let tail = if tails.len() == 1 {
+                tails.into_iter().next().unwrap()
+            }
+            else {
+                item(tails.into_iter().map(|t| (Matchable::Subexpression(t), Span::default())).collect(), false, Repetition::One)
+            };
+
+            if has_empty {
+                sequence.push(item(vec![(Matchable::Subexpression(tail), Span::default())], false, Repetition::LessThanEq(1)));
+            }
+            else {
+                sequence.push(item(vec![(Matchable::Subexpression(tail), Span::default())], false, Repetition::One));
+            }
+        }
+
+        let c = simplify_sequence(join_items(sequence));
+        if c.next.is_none() && c.repetition == Repetition::One && !c.matchables.inverted {
+            let u = c.matchables.matchable;
+            elements.extend(u.matchables.into_iter().zip(u.spans));
+        }
+        else {
+            elements.push((Matchable::Subexpression(c), Span::default()));
+        }
+    }
+    elements
+}
+
+fn next_char(c: char) -> Option<char> {
+    match c {
+        '\u{D7FF}' => Some('\u{E000}'),
+        c => char::from_u32(c as u32 + 1)
+    }
+}
+
+/// Merges all characters and ranges into as few, sorted ranges as possible
+fn merge_ranges(elements: Vec<(Matchable, Span)>) -> UnionMatchables {
+    let mut ranges = Vec::new();
+    let mut others = Vec::new();
+    for (m, span) in elements {
+        match m {
+            Matchable::Char(c) => ranges.push((c, c)),
+            Matchable::Range(a, b) => ranges.push((a, b)),
+            m => others.push((m, span)),
+        }
+    }
+
+    // Any character is already matched by `.`
+    if others.iter().any(|(m, _)| matches!(m, Matchable::Any)) {
+        ranges.clear();
+    }
+
+    ranges.sort();
+    let mut merged: Vec<(char, char)> = Vec::new();
+    for (a, b) in ranges {
+        if let Some((_, end)) = merged.last_mut() {
+            if next_char(*end).is_none_or(|n| a <= n) {
+                *end = (*end).max(b);
+                continue;
+            }
+        }
+        merged.push((a, b));
+    }
+
+    let mut matchables = merged.into_iter()
+        .map(|(a, b)| (if a == b { Matchable::Char(a) } else { Matchable::Range(a, b) }, Span::default()))
+        .collect::<Vec<_>>();
+    matchables.extend(others);
+
+    let (matchables, spans) = matchables.into_iter().unzip();
+    UnionMatchables { matchables, spans }
+}
@@ -0,0 +1,105 @@
+//! Traversal of the regex AST. Override the `visit_*` methods of interest and call the matching `walk_*`
+//! function to continue into child nodes.
+
+use crate::regex::{ChainedMatchable, InvertibleMatchable, Matchable, Repetition, Span};
+
+pub trait Visitor {
+    /// A whole sequence of items (a [`ChainedMatchable`] and all of its `next`s)
+    fn visit_sequence(&mut self, sequence: &ChainedMatchable) {
+        walk_sequence(self, sequence);
+    }
+
+    /// A single item of a sequence, ignoring its `next`
+    fn visit_item(&mut self, item: &ChainedMatchable) {
+        walk_item(self, item);
+    }
+
+    fn visit_invertible_matchable(&mut self, matchable: &InvertibleMatchable) {
+        walk_invertible_matchable(self, matchable);
+    }
+
+    fn visit_matchable(&mut self, matchable: &Matchable, span: Span) {
+        walk_matchable(self, matchable, span);
+    }
+
+    /// `span` covers the repetition operator, and is empty at the end of the item if there isn't one
+    fn visit_repetition(&mut self, _repetition: &Repetition, _span: Span) {}
+}
+
+pub fn walk_sequence<V: Visitor + ?Sized>(v: &mut V, sequence: &ChainedMatchable) {
+    for item in sequence.iter() {
+        v.visit_item(item);
+    }
+}
+
+pub fn walk_item<V: Visitor + ?Sized>(v: &mut V, item: &ChainedMatchable) {
+    v.visit_invertible_matchable(&item.matchables);
+    v.visit_repetition(&item.repetition, item.repetition_span);
+}
+
+pub fn walk_invertible_matchable<V: Visitor + ?Sized>(v: &mut V, matchable: &InvertibleMatchable) {
+    for (m, span) in matchable.matchable.iter() {
+        v.visit_matchable(m, span);
+    }
+}
+
+pub fn walk_matchable<V: Visitor + ?Sized>(v: &mut V, matchable: &Matchable, _span: Span) {
+    if let Matchable::Subexpression(c) = matchable {
+        v.visit_sequence(c);
+    }
+}
+
+/// Like [`Visitor`], but able to change the AST. Inverted groups (`[^...]`) can't be given subexpressions, as
+/// they only match single characters.
+pub trait VisitorMut {
+    /// A whole sequence of items (a [`ChainedMatchable`] and all of its `next`s)
+    fn visit_sequence_mut(&mut self, sequence: &mut ChainedMatchable) {
+        walk_sequence_mut(self, sequence);
+    }
+
+    /// A single item of a sequence, ignoring its `next`
+    fn visit_item_mut(&mut self, item: &mut ChainedMatchable) {
+        walk_item_mut(self, item);
+    }
+
+    fn visit_invertible_matchable_mut(&mut self, matchable: &mut InvertibleMatchable) {
+        walk_invertible_matchable_mut(self, matchable);
+    }
+
+    fn visit_matchable_mut(&mut self, matchable: &mut Matchable, span: &mut Span) {
+        walk_matchable_mut(self, matchable, span);
+    }
+
+    /// `span` covers the repetition operator, and is empty at the end of the item if there isn't one
+    fn visit_repetition_mut(&mut self, _repetition: &mut Repetition, _span: &mut Span) {}
+}
+
+pub fn walk_sequence_mut<V: VisitorMut + ?Sized>(v: &mut V, sequence: &mut ChainedMatchable) {
+    let mut current = Some(sequence);
+    while let Some(item) = current {
+        v.visit_item_mut(item);
+        current = item.next.as_deref_mut();
+    }
+}
+
+pub fn walk_item_mut<V: VisitorMut + ?Sized>(v: &mut V, item: &mut ChainedMatchable) {
+    v.visit_invertible_matchable_mut(&mut item.matchables);
+    v.visit_repetition_mut(&mut item.repetition, &mut item.repetition_span);
+}
+
+pub fn walk_invertible_matchable_mut<V: VisitorMut + ?Sized>(v: &mut V, matchable: &mut InvertibleMatchable) {
+    let union = &mut matchable.matchable;
+    for (m, span) in union.matchables.iter_mut().zip(union.spans.iter_mut()) {
+        v.visit_matchable_mut(m, span);
+        assert!(
+            !matchable.inverted || !matches!(m, Matchable::Subexpression(_)),
+            "Inverted groups can't contain subexpressions"
+        );
+    }
+}
+
+pub fn walk_matchable_mut<V: VisitorMut + ?Sized>(v: &mut V, matchable: &mut Matchable, _span: &mut Span) {
+    if let Matchable::Subexpression(c) = matchable {
+        v.visit_sequence_mut(c);
+    }
+}
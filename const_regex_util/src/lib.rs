@@ -7,7 +7,7 @@ fn ext() {
 
 #[inline(always)]
 pub const fn find_next_code_point(s: &str, start: usize) -> usize {
-    let marker = s.as_bytes()[0];
+    let marker = s.as_bytes()[start];
     start + code_point_len(marker)
 }
 
@@ -60,6 +60,22 @@ pub const fn next_char(s: &str, pos: usize) -> (u32, usize) {
     (ret, next)
 }
 
+/// The character ending at byte `pos` of `s`, packed like [`next_char`], and the position it starts at, or `None`
+/// if `pos` is 0 or isn't a character boundary of `s`
+#[inline(always)]
+pub const fn prev_char(s: &str, pos: usize) -> Option<(u32, usize)> {
+    if pos == 0 || !s.is_char_boundary(pos) {
+        return None;
+    }
+    let bs = s.as_bytes();
+    let mut start = pos - 1;
+    // Skip back over continuation bytes
+    while bs[start] >> 6 == 0b10 {
+        start -= 1;
+    }
+    Some((next_char(s, start).0, start))
+}
+
 pub struct CharSlice<'a> {
     offset: usize,
     // next_offset: Option<usize>,
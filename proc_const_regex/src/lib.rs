@@ -1,111 +1,308 @@
-use const_regex_regex_transformer::automata::{to_dfa, to_nfa};
-use const_regex_regex_transformer::to_regex;
-use proc_macro2::{Ident, TokenStream};
-use quote::{quote, TokenStreamExt};
-use syn::{parse_macro_input, parse_str, Lit};
+use const_regex_regex_transformer::analysis::is_subset;
+use const_regex_regex_transformer::automata::DFA;
+use const_regex_regex_transformer::options::{CompileOptions, Construction};
+use const_regex_regex_transformer::set::{try_to_set, RegexSet};
+use const_regex_regex_transformer::{try_to_automata, try_to_regex};
+use proc_macro2::Span;
+use quote::{format_ident, quote, ToTokens};
+use syn::parse::Parser;
+use syn::punctuated::Punctuated;
+use syn::{Lit, LitStr, Token};
 
 
+/// Parses `count` string literals (or, if `count` is `None`, at least one) optionally followed by
+/// `, option = value` pairs setting fields of [`CompileOptions`]
+fn parse_args(input: proc_macro::TokenStream, count: Option<usize>) -> syn::Result<(Vec<LitStr>, CompileOptions)> {
+    let args = Punctuated::<syn::Expr, Token![,]>::parse_terminated.parse(input)?;
+    let mut args = args.into_iter().peekable();
+
+    // Match on the parsed input to find the string literals
+    let mut regexes = Vec::new();
+    while count.is_none_or(|count| regexes.len() < count) {
+        regexes.push(match args.peek() {
+            Some(syn::Expr::Lit(syn::ExprLit { lit: Lit::Str(lit_str), .. })) => lit_str.clone(),
+            _ if count.is_none() && !regexes.is_empty() => break,
+            Some(e) => return Err(syn::Error::new_spanned(e, "Expected a string literal as input.")),
+            None => return Err(syn::Error::new(Span::call_site(), "Expected a string literal as input.")),
+        });
+        args.next();
+    }
+
+    let mut options = CompileOptions::default();
+    for arg in args {
+        let syn::Expr::Assign(assign) = &arg else {
+            return Err(syn::Error::new_spanned(arg, "Expected `option = value`"));
+        };
+        let value = assign.right.as_ref();
+        let int = || match value {
+            syn::Expr::Lit(syn::ExprLit { lit: Lit::Int(i), .. }) => i.base10_parse::<u64>(),
+            e => Err(syn::Error::new_spanned(e, "Expected an integer literal")),
+        };
+        match assign.left.to_token_stream().to_string().as_str() {
+            "max_nfa_states" => options.max_nfa_states = int()?,
+            "max_dfa_states" => options.max_dfa_states = int()?,
+            "construction" => {
+                options.construction = match value.to_token_stream().to_string().as_str() {
+                    "Thompson" => Construction::Thompson,
+                    "Glushkov" => Construction::Glushkov,
+                    "Brzozowski" => Construction::Brzozowski,
+                    _ => return Err(syn::Error::new_spanned(value, "Expected `Thompson`, `Glushkov` or `Brzozowski`")),
+                }
+            }
+            _ => return Err(syn::Error::new_spanned(
+                &assign.left,
+                "Unknown option. Expected `max_nfa_states`, `max_dfa_states` or `construction`"
+            )),
+        }
+    }
+
+    Ok((regexes, options))
+}
+
 #[proc_macro]
 pub fn regex(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
-    // Parse the input TokenStream into a Rust syntax element
-    let input = parse_macro_input!(input as syn::Expr);
-
-    // Match on the parsed input to find a string literal
-    let extracted_string = if let syn::Expr::Lit(syn::ExprLit { lit: Lit::Str(lit_str), .. }) = input {
-        lit_str.value() // Extract the inner string
-    } else {
-        panic!("Expected a string literal as input.");
+    let (regexes, options) = match parse_args(input, Some(1)) {
+        Ok(args) => args,
+        Err(e) => return e.to_compile_error().into(),
     };
 
-    let r = to_regex(&extracted_string);
-    let nfa = to_nfa(r);
+    let dfa = match compile(&regexes[0], &options) {
+        Ok(dfa) => dfa,
+        Err(e) => return e.to_compile_error().into(),
+    };
 
-    println!("{:?}\n", nfa);
+    match dfa.try_to_tokens(&options) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => syn::Error::new(regexes[0].span(), e).to_compile_error().into(),
+    }
+}
 
-    let dfa = to_dfa(nfa);
+/// Compiles the regex in `lit_str`, rejecting regexes that can never match anything
+fn compile(lit_str: &LitStr, options: &CompileOptions) -> syn::Result<DFA> {
+    let dfa = compile_any(lit_str, options)?;
+    if dfa.is_empty() {
+        return Err(syn::Error::new(lit_str.span(), "Regex can never match anything"));
+    }
+    Ok(dfa)
+}
 
-    println!("{:?}", dfa);
+/// Compiles the regex in `lit_str`, even if it can never match anything
+fn compile_any(lit_str: &LitStr, options: &CompileOptions) -> syn::Result<DFA> {
+    let regex = try_to_regex(&lit_str.value()).map_err(|e| syn::Error::new(lit_str.span(), e))?;
+    try_to_automata(regex, options).map_err(|e| syn::Error::new(lit_str.span(), e))
+}
 
-    let mut transitions = TokenStream::new();
+/// Compiles several regexes into one matcher, e.g. `regex_set!["[0-9]+", "[a-z]+", "0x[0-9a-f]+"]`, with
+/// `const fn matches(&self, &str) -> [bool; N]` giving whether each regex matches and
+/// `const fn first_match(&self, &str) -> Option<usize>` giving the first one that does. Takes the same options as
+/// `regex!`.
+#[proc_macro]
+pub fn regex_set(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let (regexes, options) = match parse_args(input, None) {
+        Ok(args) => args,
+        Err(e) => return e.to_compile_error().into(),
+    };
 
-    for (i, (s, ts)) in dfa.transitions.iter().enumerate() {
-        let success_ident: Ident = parse_str(&format!("T{i}_SUCCESS")).unwrap();
-        let ident: Ident = parse_str(&format!("T{i}")).unwrap();
-        let s = *s;
-        let t_len = ts.len();
+    match compile_set(&regexes, &options) {
+        Ok(set) => set.into_token_stream().into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
 
-        let mut ts_tokens = TokenStream::new();
+/// Compiles the regexes in `lit_strs` into a set, rejecting regexes that can never match anything
+fn compile_set(lit_strs: &[LitStr], options: &CompileOptions) -> syn::Result<RegexSet> {
+    let regexes = lit_strs.iter()
+        .map(|r| try_to_regex(&r.value()).map_err(|e| syn::Error::new(r.span(), e)))
+        .collect::<syn::Result<Vec<_>>>()?;
+    let set = try_to_set(regexes, options).map_err(|e| syn::Error::new(Span::call_site(), e))?;
+    let never = lit_strs.iter().enumerate()
+        .filter(|(id, _)| !set.matches.iter().any(|ids| ids.contains(*id)))
+        .map(|(_, r)| syn::Error::new(r.span(), "Regex can never match anything"))
+        .reduce(|mut a, b| { a.combine(b); a });
+    match never {
+        Some(e) => Err(e),
+        None => Ok(set),
+    }
+}
 
-        for (tt, dst) in ts {
-            let dst = *dst;
-            let tt = *tt;
+/// Generates a lexer from an enum whose variants are each given a regex, e.g.
+///
+/// ```ignore
+/// lexer! {
+///     pub enum Token {
+///         Number = "[0-9]+",
+///         Ident = "[a-z_]+",
+///         Plus = "\\+",
+///         #[skip]
+///         Whitespace = "[ \t\n]+",
+///     }
+/// }
+/// ```
+///
+/// `Token::lex(input)` then iterates over `(Token, span)` pairs, always taking the longest possible token and
+/// breaking ties in favour of the earliest variant. Variants marked `#[skip]` are matched but not returned. Lexing
+/// stops at the first character no variant matches, which `TokenLexer::remainder` shows. The enum derives `Debug`,
+/// `Clone`, `Copy`, `PartialEq`, `Eq` and `Hash`.
+#[proc_macro]
+pub fn lexer(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let mut item = match syn::parse::<syn::ItemEnum>(input) {
+        Ok(item) => item,
+        Err(e) => return e.to_compile_error().into(),
+    };
+    if !item.generics.params.is_empty() {
+        return syn::Error::new_spanned(&item.generics, "Lexer enums can't be generic").to_compile_error().into();
+    }
 
-            ts_tokens.append_all::<TokenStream>(quote! {
-                (#tt, #dst),
-            }.into());
+    let mut regexes = Vec::new();
+    let mut skip = Vec::new();
+    for variant in &mut item.variants {
+        if !matches!(variant.fields, syn::Fields::Unit) {
+            return syn::Error::new_spanned(&variant.fields, "Lexer variants can't have fields").to_compile_error().into();
         }
-
-        transitions.append_all::<TokenStream>(quote! {
-            const #success_ident: bool = #s;
-            const #ident: [(const_regex_regex_transformer::automata::TransitionType, usize); #t_len] = [#ts_tokens];
-        }.into());
+        match variant.discriminant.take() {
+            Some((_, syn::Expr::Lit(syn::ExprLit { lit: Lit::Str(lit_str), .. }))) => regexes.push(lit_str),
+            _ => return syn::Error::new_spanned(&variant.ident, "Expected `Variant = \"regex\"`").to_compile_error().into(),
+        }
+        let attrs = variant.attrs.len();
+        variant.attrs.retain(|a| !a.path().is_ident("skip"));
+        skip.push(variant.attrs.len() != attrs);
     }
 
-    let mut full_tokens = TokenStream::new();
-    for i in 0..dfa.transitions.len() {
-        let success_ident: Ident = parse_str(&format!("T{i}_SUCCESS")).unwrap();
-        let ident: Ident = parse_str(&format!("T{i}")).unwrap();
-        full_tokens.append_all::<TokenStream>(quote! {
-            (#success_ident, &#ident),
-        }.into());
+    let set = match compile_set(&regexes, &CompileOptions::default()) {
+        Ok(set) => set,
+        Err(e) => return e.to_compile_error().into(),
+    };
+    if let Some(e) = set.matches[0].iter()
+        .map(|id| syn::Error::new(regexes[id].span(), "Lexer rules can't match the empty string"))
+        .reduce(|mut a, b| { a.combine(b); a }) {
+        return e.to_compile_error().into();
     }
 
-    let t_len = dfa.transitions.len();
-    transitions.append_all::<TokenStream>(quote! {
-        const TRANSITIONS: [(bool, &[(const_regex_regex_transformer::automata::TransitionType, usize)]); #t_len] = [#full_tokens];
-    }.into());
+    let (vis, name) = (&item.vis, &item.ident);
+    let lexer = format_ident!("{name}Lexer");
+    let variants = item.variants.iter().map(|v| &v.ident);
+    let count = regexes.len();
+    let longest_match = set.longest_match_fn();
+    quote! {
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        #item
 
-    let x = quote! {
-        {
-            struct Regex;
-
-            impl Regex {
-                pub const fn test(&self, input: &str) -> bool {
-                    #transitions
-
-                    let mut state = 0;
-                let mut string_pos = 0;
-                'outer: loop {
-                    if state >= TRANSITIONS.len() { panic!("Invalid DFA state!"); }
-                    let (success_state, ts) = &TRANSITIONS[state];
-                    if string_pos == input.as_bytes().len() { return *success_state; }
-                    let (c, d) = const_regex_util::next_char(input, string_pos);
-                    string_pos = d;
-                    let mut i = 0;
-                    let len = ts.len();
-                    while i < len {
-                        let (t, ns) = &ts[i];
-                        let r = match t {
-                            const_regex_regex_transformer::automata::TransitionType::Single(a) => *a == c,
-                            const_regex_regex_transformer::automata::TransitionType::Range(a, b) => *a <= c && c <= *b,
-                            const_regex_regex_transformer::automata::TransitionType::ExcludeRange(a, b) => c < *a || *b > c,
-                            const_regex_regex_transformer::automata::TransitionType::Any => true
-                        };
-                        if r {
-                            state = *ns;
-                            continue 'outer;
-                        }
-                        i += 1;
-                    }
-                    return false;
+        impl #name {
+            const RULES: [Self; #count] = [#(Self::#variants),*];
+            const SKIP: [bool; #count] = [#(#skip),*];
+
+            /// Lexes `input` into tokens and their spans in bytes
+            #vis fn lex(input: &str) -> #lexer<'_> {
+                #lexer { input, pos: 0 }
+            }
+
+            /// The longest token at the start of `input[start..]` (the earliest variant if several match it), and
+            /// where it ends. `None` if there isn't one or `start` isn't a character boundary of `input`.
+            #vis const fn next_token(input: &str, start: usize) -> Option<(Self, usize)> {
+                #longest_match
+
+                if !input.is_char_boundary(start) {
+                    return None;
+                }
+                match longest_match(input, start) {
+                    Some((id, end)) => Some((Self::RULES[id], end)),
+                    None => None,
+                }
+            }
+        }
+
+        /// Iterator over the tokens in a string
+        #vis struct #lexer<'a> {
+            input: &'a str,
+            pos: usize,
+        }
+
+        impl<'a> #lexer<'a> {
+            /// The input that hasn't been lexed yet. Once iteration has finished, this is empty unless no variant
+            /// matched what was left.
+            #vis fn remainder(&self) -> &'a str {
+                &self.input[self.pos..]
+            }
+        }
+
+        impl Iterator for #lexer<'_> {
+            type Item = (#name, std::ops::Range<usize>);
+
+            fn next(&mut self) -> Option<Self::Item> {
+                loop {
+                    let (token, end) = #name::next_token(self.input, self.pos)?;
+                    let span = self.pos..end;
+                    self.pos = end;
+                    if !#name::SKIP[token as usize] {
+                        return Some((token, span));
                     }
                 }
             }
-            Regex {}
         }
-};
+    }.into()
+}
+
+/// Emits the same matcher as `regex!` from a DFA serialised with `DFA::to_bytes` (or `DFA::to_json` with the
+/// `json` feature, for paths ending in `.json`). The path is relative to the crate's `Cargo.toml`.
+#[proc_macro]
+pub fn include_dfa(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let lit_str = match syn::parse::<LitStr>(input) {
+        Ok(l) => l,
+        Err(e) => return syn::Error::new(e.span(), "Expected a string literal path").to_compile_error().into(),
+    };
+
+    let path = std::path::Path::new(&std::env::var("CARGO_MANIFEST_DIR").unwrap_or_default()).join(lit_str.value());
+    let bytes = match std::fs::read(&path) {
+        Ok(bytes) => bytes,
+        Err(e) => return syn::Error::new(lit_str.span(), format!("Couldn't read {}: {e}", path.display())).to_compile_error().into(),
+    };
+
+    let dfa = if path.extension().is_some_and(|e| e == "json") {
+        #[cfg(feature = "json")]
+        { DFA::from_json(&String::from_utf8_lossy(&bytes)) }
+        #[cfg(not(feature = "json"))]
+        { return syn::Error::new(lit_str.span(), "Loading JSON DFAs needs the `json` feature").to_compile_error().into(); }
+    }
+    else {
+        DFA::from_bytes(&bytes)
+    };
+    let dfa = match dfa {
+        Ok(dfa) => dfa,
+        Err(e) => return syn::Error::new(lit_str.span(), e).to_compile_error().into(),
+    };
+
+    // Recompile when the file changes
+    let path = path.to_string_lossy();
+    quote! {
+        {
+            const _: &[u8] = include_bytes!(#path);
+            #dfa
+        }
+    }.into()
+}
+
+/// Fails compilation unless every string matched by the first regex is matched by the second, e.g.
+/// `const_assert_subset!("[0-9]{3}", "[0-9]+");`. Takes the same options as `regex!`.
+#[proc_macro]
+pub fn const_assert_subset(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let (regexes, options) = match parse_args(input, Some(2)) {
+        Ok(args) => args,
+        Err(e) => return e.to_compile_error().into(),
+    };
+
+    // A regex that never matches is trivially a subset, so empty languages are fine here
+    let (a, b) = match (compile_any(&regexes[0], &options), compile_any(&regexes[1], &options)) {
+        (Ok(a), Ok(b)) => (a, b),
+        (Err(e), _) | (_, Err(e)) => return e.to_compile_error().into(),
+    };
 
-x.into()
+    match is_subset(&a, &b) {
+        Ok(()) => quote! { const _: () = (); }.into(),
+        Err(counterexample) => syn::Error::new(
+            regexes[0].span(),
+            format!("{:?} matches {counterexample:?}, which {:?} doesn't", regexes[0].value(), regexes[1].value())
+        ).to_compile_error().into(),
+    }
 }
 
 // fn convert_regex(regex: ChainedMatchable) -> Final {
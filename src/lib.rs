@@ -1,15 +1,745 @@
-
-
 #[cfg(test)]
 mod tests {
     // const A: bool = regex!("123").test("123");
     // const B: bool = regex!("124").test("123");
 
-    use const_regex_regex_transformer::automata::{test_nfa, to_nfa};
-    use const_regex_regex_transformer::to_regex;
+    use const_regex_regex_transformer::analysis::{is_equivalent, is_subset};
+    use const_regex_regex_transformer::automata::{test_nfa, to_nfa, try_to_nfa, TransitionType, DFA};
+    use const_regex_regex_transformer::builder::{Class, Pattern};
+    use const_regex_regex_transformer::derivative::{self, Re};
+    use const_regex_regex_transformer::error::{CompileError, DecodeError, ParseError};
+    use const_regex_regex_transformer::lazy::LazyDFA;
+    use const_regex_regex_transformer::options::{CompileOptions, Construction};
+    use const_regex_regex_transformer::rank::{rank, unrank};
+    use const_regex_regex_transformer::regex::{parse_regex, Matchable, Repetition, Span};
+    use const_regex_regex_transformer::sample::Sampler;
+    use const_regex_regex_transformer::set::try_to_set;
+    use const_regex_regex_transformer::simplify::simplify;
+    use const_regex_regex_transformer::{to_automata, to_regex, try_to_automata, try_to_regex};
+    use const_regex_regex_transformer::visit::{walk_matchable, walk_matchable_mut, Visitor, VisitorMut};
+    use proc_const_regex::{const_assert_subset, include_dfa, lexer, regex, regex_set};
+    use quote::ToTokens;
 
     #[test]
     fn it_works() {
 
     }
+
+    #[test]
+    fn builder() {
+        let p = Pattern::lit("hell")
+            .then(Pattern::class('a'..='z').repeat(1..))
+            .then(Pattern::char('o').plus());
+        assert_eq!(p.clone().build(), to_regex("hell[a-z]+o+"));
+
+        let nfa = to_nfa(p.build());
+        assert!(test_nfa(&nfa, "helloo"));
+        assert!(test_nfa(&nfa, "hellxxo"));
+        assert!(!test_nfa(&nfa, "hell1o"));
+
+        let p = Pattern::lit("cat")
+            .or(Pattern::lit("dog"))
+            .then(Pattern::class(Class::new().chars("sz")).optional())
+            .then(Pattern::any().times(2));
+        let nfa = to_nfa(p.build());
+        assert!(test_nfa(&nfa, "cat12"));
+        assert!(test_nfa(&nfa, "dogs.."));
+        assert!(!test_nfa(&nfa, "dogss.."));
+        assert!(!test_nfa(&nfa, "cow12"));
+    }
+
+    #[test]
+    fn utf8_decoding() {
+        use const_regex_util::{char_to_utf8, find_next_code_point, next_char};
+
+        let s = "a€𝄞é";
+        assert_eq!(find_next_code_point(s, 0), 1);
+        assert_eq!(find_next_code_point(s, 1), 4);
+        assert_eq!(find_next_code_point(s, 4), 8);
+        assert_eq!(next_char(s, 1), (char_to_utf8('€'), 4));
+        assert_eq!(next_char(s, 4), (char_to_utf8('𝄞'), 8));
+        assert_eq!(next_char(s, 8), (char_to_utf8('é'), 10));
+    }
+
+    #[test]
+    fn display_round_trip() {
+        for p in [
+            "hell[a-z]+o+",
+            "ab|cd",
+            "(a|(b|c))*x{2}y{1,3}z{,4}w{5,}",
+            r"\.\*\\\(\)\[\]\{\}\|\^\$",
+            r"[\^a\-z\]][^0-9_]\n",
+            "(ab)?.+",
+        ] {
+            let ast = to_regex(p);
+            assert_eq!(ast.to_string(), p);
+            assert_eq!(to_regex(&ast.to_string()), ast);
+        }
+
+        let ast = Pattern::lit("a.b")
+            .or(Pattern::class(Class::new().chars("]-").negate()))
+            .plus()
+            .then(Pattern::lit("{x}").times(3))
+            .build();
+        assert_eq!(ast.to_string(), r"(a\.b|[^\]\-])+(\{x\}){3}");
+        assert_eq!(to_regex(&ast.to_string()), ast);
+
+        for p in [r"\d", r"a\w+", r"[\s]", r"\b", r"x\"] {
+            assert!(parse_regex(p, false).is_err(), "{p}");
+        }
+        assert_eq!(try_to_regex(r"a\d"), Err(ParseError::UnsupportedEscape { offset: 1 }));
+        assert_eq!(try_to_regex("a)b"), Err(ParseError::UnmatchedParen { offset: 1 }));
+        assert_eq!(try_to_regex("a(b"), Err(ParseError::Syntax { offset: 3 }));
+
+        // `.` in an inverted group is written as the range of every character
+        struct InvertAny;
+        impl VisitorMut for InvertAny {
+            fn visit_matchable_mut(&mut self, matchable: &mut Matchable, span: &mut Span) {
+                if *matchable == Matchable::Char('a') {
+                    *matchable = Matchable::Any;
+                }
+                walk_matchable_mut(self, matchable, span);
+            }
+        }
+        let mut ast = to_regex("[^ab]x|y");
+        InvertAny.visit_sequence_mut(&mut ast);
+        assert_eq!(ast.to_string(), "[^\0-\u{10FFFF}b]x|y");
+        let parsed = to_regex(&ast.to_string());
+        assert_eq!(parsed.to_string(), ast.to_string());
+        assert!(is_equivalent(&to_automata(parsed), &to_automata(ast)).is_ok());
+
+        // An empty union matches nothing, so it's written as the inverted group of every character
+        let nothing = to_regex("[^\0-\u{10FFFF}]");
+        assert_eq!(nothing.to_string(), "[^\0-\u{10FFFF}]");
+        assert!(to_automata(nothing).is_empty());
+
+        // Runs of characters in an alternation are written as one branch
+        let ast = simplify(to_regex("a|c|xy|[0-9]"));
+        assert_eq!(ast.to_string(), "([0-9ac]|xy)");
+        assert_eq!(simplify(to_regex(&ast.to_string())), ast);
+    }
+
+    #[test]
+    fn spans_and_visitor() {
+        struct Leaves<'a>(&'a str, Vec<&'a str>);
+        impl Visitor for Leaves<'_> {
+            fn visit_matchable(&mut self, matchable: &Matchable, span: Span) {
+                if !matches!(matchable, Matchable::Subexpression(_)) {
+                    self.1.push(&self.0[span.start..span.end]);
+                }
+                walk_matchable(self, matchable, span);
+            }
+        }
+
+        let p = r"ab|(c\.)+[x-z_]€";
+        let ast = to_regex(p);
+        let mut v = Leaves(p, Vec::new());
+        v.visit_sequence(&ast);
+        assert_eq!(v.1, ["a", "b", "c", r"\.", "x-z", "_", "€"]);
+
+        let Matchable::Subexpression(second) = &ast.matchables().matchable().matchables()[1] else { panic!() };
+        let spans = second.iter().map(|c| &p[c.span().start..c.span().end]).collect::<Vec<_>>();
+        assert_eq!(spans, [r"(c\.)+", "[x-z_]", "€"]);
+        assert_eq!(&p[second.full_span().start..second.full_span().end], r"(c\.)+[x-z_]€");
+
+        struct Quantifiers<'a>(&'a str, Vec<&'a str>);
+        impl Visitor for Quantifiers<'_> {
+            fn visit_repetition(&mut self, _repetition: &Repetition, span: Span) {
+                self.1.push(&self.0[span.start..span.end]);
+            }
+        }
+        let p = "a{2,3}(bc)+d";
+        let mut v = Quantifiers(p, Vec::new());
+        v.visit_sequence(&to_regex(p));
+        // `b` and `c` aren't repeated, so they have empty spans
+        assert_eq!(v.1, ["{2,3}", "", "", "+", ""]);
+    }
+
+    #[test]
+    fn simplification() {
+        for (p, expected) in [
+            ("abc|abd", "ab[c-d]"),
+            ("a|b|c|e|(x|y)", "[a-cex-y]"),
+            ("(a)(bc){1}x{0,}(y){2}", "abcx*y{2}"),
+            ("ab|abc|abcd", "ab(cd?)?"),
+            ("(if|else|elif)+", "(if|el(se|if))+"),
+            ("[a-cb-dx]", "[a-dx]"),
+            ("xx*", "x+"),
+            ("a{2}a?b", "a{2,3}b"),
+        ] {
+            let ast = simplify(to_regex(p));
+            assert_eq!(ast.to_string(), expected, "{p}");
+
+            let (a, b) = (to_nfa(to_regex(p)), to_nfa(ast));
+            for s in ["ab", "abc", "abd", "abcd", "c", "e", "y", "abcxxyy", "ifelif", "elifels", "b", "x"] {
+                assert_eq!(test_nfa(&a, s), test_nfa(&b, s), "{p} {s}");
+            }
+        }
+    }
+
+    #[test]
+    fn bounded_repetition() {
+        let nfa = to_nfa(to_regex("x[a-z]{2,300}y"));
+        assert!(!test_nfa(&nfa, "xay"));
+        assert!(test_nfa(&nfa, "xaby"));
+        assert!(test_nfa(&nfa, &format!("x{}y", "q".repeat(300))));
+        assert!(!test_nfa(&nfa, &format!("x{}y", "q".repeat(301))));
+
+        let options = CompileOptions::default();
+        let e = try_to_nfa(to_regex("a{1,18446744073709551615}"), &options).unwrap_err();
+        assert_eq!(e, CompileError::TooManyNfaStates { limit: options.max_nfa_states, required: u64::MAX });
+
+        let options = CompileOptions { max_nfa_states: 20, ..CompileOptions::default() };
+        assert!(try_to_nfa(to_regex("(ab){5}"), &options).is_ok());
+        assert!(try_to_nfa(to_regex("(ab){50}"), &options).is_err());
+
+        // The size checked against the limit is exactly the number of states built
+        for p in ["(ab)*", "((ab)*c)+", "(a(b|cd)*){2,3}", "((a|b)+c?){2}", "(x(y)*)*z", "(a*b{2,})?", "[a-c]*"] {
+            let required = to_nfa(to_regex(p)).state_count() as u64;
+            let options = CompileOptions { max_nfa_states: required, ..CompileOptions::default() };
+            assert!(try_to_nfa(to_regex(p), &options).is_ok(), "{p}");
+            let options = CompileOptions { max_nfa_states: required - 1, ..CompileOptions::default() };
+            let e = try_to_nfa(to_regex(p), &options).unwrap_err();
+            assert_eq!(e, CompileError::TooManyNfaStates { limit: required - 1, required }, "{p}");
+        }
+
+        assert!(regex!("[0-9]{1,200}", max_nfa_states = 500).test("1234"));
+    }
+
+    #[test]
+    fn subset_construction() {
+        const HELLO: bool = regex!("hell[a-z]+o+").test("helloo");
+        const HELLXXO: bool = regex!("hell[a-z]+o+").test("hellxxo");
+        const HELLXX: bool = regex!("hell[a-z]+o+").test("hellxx");
+        assert_eq!((HELLO, HELLXXO, HELLXX), (true, true, false));
+        assert!(regex!("[^a-y]b|zc").test("zc"));
+        assert!(regex!("[^a-y]b|zc").test("€b"));
+        assert!(!regex!("[^a-y]b|zc").test("ab"));
+
+        // Transitions out of every state must not overlap
+        let dfa = to_automata(to_regex("[a-z]+o|[^x]*x|.m"));
+        for (_, ts) in &dfa.transitions {
+            let mut ranges = ts.iter().flat_map(|(t, _)| t.codepoint_ranges()).collect::<Vec<_>>();
+            ranges.sort_by_key(|r| *r.start());
+            assert!(ranges.windows(2).all(|w| w[0].end() < w[1].start()));
+        }
+
+        let keywords = (0..500).map(|i| format!("kw{}x{i}", i % 7)).collect::<Vec<_>>().join("|");
+        let dfa = to_automata(to_regex(&keywords));
+        assert!(dfa.transitions.len() < 2000);
+
+        let options = CompileOptions { max_dfa_states: 1000, ..CompileOptions::default() };
+        let e = try_to_automata(to_regex("[ab]*a[ab]{12}"), &options).unwrap_err();
+        assert_eq!(e, CompileError::TooManyDfaStates { limit: 1000 });
+    }
+
+    #[test]
+    fn pike_vm() {
+        let nfa = to_nfa(to_regex("(a*)*b"));
+        let input = "a".repeat(10_000);
+        assert!(!test_nfa(&nfa, &input));
+        assert!(test_nfa(&nfa, &(input + "b")));
+
+        let nfa = to_nfa(to_regex("((x|y)?z*)+€"));
+        let long = "xzyz".repeat(100_000) + "€";
+        assert!(test_nfa(&nfa, &long));
+        assert!(!test_nfa(&nfa, &(long + "x")));
+        assert!(test_nfa(&nfa, "xzzyz€"));
+        assert!(test_nfa(&nfa, "€"));
+    }
+
+    #[test]
+    fn lazy_dfa() {
+        // The full DFA has ~2^20 states
+        let regex = to_regex("[ab]*a[ab]{20}");
+        assert!(try_to_automata(regex.clone(), &CompileOptions::default()).is_err());
+
+        let mut dfa = LazyDFA::from_regex(regex, 64, &CompileOptions::default()).unwrap();
+        let matching = format!("{}a{}", "ab".repeat(50), "b".repeat(20));
+        for _ in 0..3 {
+            assert!(dfa.test(&matching));
+            assert!(!dfa.test(&format!("{matching}a")));
+            assert!(!dfa.test("ab"));
+            assert!(dfa.cached_states() <= 64);
+        }
+
+        let mut dfa = LazyDFA::new(to_nfa(to_regex("hell[a-z]+o+")), 2);
+        assert!(dfa.test("hellxxo"));
+        assert!(!dfa.test("hell1o"));
+
+        // Small caches are cleared all the time but must still give the right answers
+        let regex = to_regex("(a|b)*abb(a|b)*c");
+        let full = to_automata(regex.clone());
+        for capacity in [1, 2, 3, 5] {
+            let mut dfa = LazyDFA::new(to_nfa(regex.clone()), capacity);
+            for s in ["abbc", "babbabac", "ababc", "abb", "bbabbbbbbc", "c", ""] {
+                assert_eq!(dfa.test(s), full.test(s), "{capacity} {s}");
+                assert!(dfa.cached_states() <= capacity.max(2), "{capacity} {s}");
+            }
+        }
+    }
+
+    #[test]
+    fn glushkov() {
+        let options = CompileOptions { construction: Construction::Glushkov, ..CompileOptions::default() };
+        let inputs = ["", "a", "ab", "abab", "aab", "b", "ba", "abc", "c", "cc", "ccc", "acbc", "xyz", "ac"];
+        for p in ["a*", "(ab)+", "a?b{1,2}", "(a|b)*c{2,}", "[^b]*(bc)?", "((a|b)?c*)+", "(a*)*b", "ab|ac|x.z"] {
+            let thompson = to_nfa(to_regex(p));
+            let glushkov = try_to_nfa(to_regex(p), &options).unwrap();
+            for s in inputs {
+                assert_eq!(test_nfa(&thompson, s), test_nfa(&glushkov, s), "{p} {s}");
+            }
+        }
+
+        assert!(regex!("(a|b)*abb", construction = Glushkov).test("babaabb"));
+    }
+
+    #[test]
+    fn brzozowski() {
+        let options = CompileOptions { construction: Construction::Brzozowski, ..CompileOptions::default() };
+        let inputs = ["", "a", "ab", "abab", "aab", "b", "ba", "abc", "c", "cc", "ccc", "acbc", "xyz", "ac", "€"];
+        for p in ["a*", "(ab)+", "a?b{1,2}", "(a|b)*c{2,}", "[^b]*(bc)?", "((a|b)?c*)+", "(a*)*b", "ab|ac|x.z"] {
+            let expected = to_automata(to_regex(p));
+            let dfa = try_to_automata(to_regex(p), &options).unwrap();
+            assert!(dfa.transitions.len() <= expected.transitions.len(), "{p}");
+            for s in inputs {
+                assert_eq!(expected.test(s), dfa.test(s), "{p} {s}");
+            }
+        }
+
+        // Contains "ab" but doesn't end with "b"
+        let re = Re::difference(Re::from_regex(&to_regex(".*ab.*")), Re::from_regex(&to_regex(".*b")));
+        let dfa = derivative::to_dfa(re, &options).unwrap();
+        assert!(dfa.test("xaba"));
+        assert!(!dfa.test("xab"));
+        assert!(!dfa.test("ba"));
+
+        assert!(regex!("(a|b)*abb", construction = Brzozowski).test("babaabb"));
+        assert!(!regex!("(a|b)*abb", construction = Brzozowski).test("babaab"));
+    }
+
+    #[test]
+    fn dfa_algebra() {
+        let words = to_automata(to_regex("[a-z]+"));
+        let has_digit = to_automata(to_regex(".*[0-9].*"));
+        let short = to_automata(to_regex(".{0,3}"));
+
+        let union = words.union(&has_digit);
+        let intersection = has_digit.intersection(&short);
+        let difference = words.difference(&short);
+        let complement = short.complement();
+        let reverse = to_automata(to_regex("ab+c")).reverse();
+        for (s, u, i, d, c, r) in [
+            ("", false, false, false, false, false),
+            ("abc", true, false, false, false, false),
+            ("abcd", true, false, true, true, false),
+            ("a1", true, true, false, false, false),
+            ("€€1€", true, false, false, true, false),
+            ("--", false, false, false, false, false),
+            ("cbba", true, false, true, true, true),
+            ("cba", true, false, false, false, true),
+        ] {
+            assert_eq!(union.test(s), u, "union {s}");
+            assert_eq!(intersection.test(s), i, "intersection {s}");
+            assert_eq!(difference.test(s), d, "difference {s}");
+            assert_eq!(complement.test(s), c, "complement {s}");
+            assert_eq!(reverse.test(s), r, "reverse {s}");
+        }
+
+        // Disjoint languages leave only the start state
+        assert_eq!(words.intersection(&has_digit).transitions.len(), 1);
+
+        let tokens = union.to_token_stream().to_string();
+        assert!(tokens.contains("pub const fn test"));
+    }
+
+    #[test]
+    fn equivalence_and_inclusion() {
+        let dfa = |p: &str| to_automata(to_regex(p));
+        assert_eq!(is_subset(&dfa("[a-z]{2,4}"), &dfa("[a-z]+")), Ok(()));
+        assert_eq!(is_subset(&dfa("[a-z]+"), &dfa("[a-z]{2,4}")), Err("a".to_string()));
+        assert_eq!(is_subset(&dfa("[0-9]{3}-?[0-9]{4}"), &dfa("[0-9]{3}-[0-9]{4}")), Err("0000000".to_string()));
+
+        assert_eq!(is_equivalent(&dfa("(a|b)*"), &dfa("(a*b*)*")), Ok(()));
+        assert_eq!(is_equivalent(&dfa("(ab)+|c"), &dfa("c|ab(ab)*")), Ok(()));
+        assert_eq!(is_equivalent(&dfa("a*"), &dfa("a+")), Err(String::new()));
+        assert_eq!(is_equivalent(&dfa("x[^a]"), &dfa("x.")), Err("xa".to_string()));
+
+        const_assert_subset!("[0-9]{3}", "[0-9]+");
+        const_assert_subset!("(cat|dog)s?", "[a-z]{3,4}", construction = Glushkov);
+        const_assert_subset!("[^\0-\u{10FFFF}]", "x");
+        const_assert_subset!("[^\0-\u{10FFFF}]", "[^\0-\u{10FFFF}]");
+    }
+
+    #[test]
+    fn witnesses() {
+        let dfa = to_automata(to_regex("[a-c]{2}x+|q"));
+        assert_eq!(dfa.shortest_accepted().as_deref(), Some("q"));
+        assert_eq!(dfa.shortest_rejected().as_deref(), Some(""));
+        assert!(!dfa.is_empty());
+
+        let dfa = to_automata(to_regex(".*"));
+        assert_eq!(dfa.shortest_accepted().as_deref(), Some(""));
+        assert_eq!(dfa.shortest_rejected(), None);
+        assert_eq!(to_automata(to_regex(".?")).shortest_rejected().as_deref(), Some("  "));
+
+        assert!(to_automata(to_regex("[^\u{0}-\u{10FFFF}]")).is_empty());
+        assert!(to_automata(to_regex("[a-z]+")).intersection(&to_automata(to_regex("[0-9]+"))).is_empty());
+
+        let r = regex!("[0-9]{3}-[0-9]{4}");
+        assert_eq!(r.shortest_match(), Some("000-0000"));
+        assert!(r.test(r.shortest_match().unwrap()));
+        assert!(!r.test(r.shortest_non_match().unwrap()));
+    }
+
+    #[test]
+    fn sampling() {
+        let dfa = to_automata(to_regex("[ab]{1,2}c?|x[\u{D7FF}-\u{E000}]"));
+        let sampler = Sampler::new(dfa.clone(), ..=3);
+        assert_eq!(sampler.len(), 2 + 4 + 2 + 4 + 2);
+        let all = (0..sampler.len()).map(|n| sampler.get(n).unwrap()).collect::<std::collections::HashSet<_>>();
+        assert_eq!(all.len(), 14);
+        assert!(all.iter().all(|s| dfa.test(s)));
+        assert!(all.contains("x\u{E000}"));
+        assert_eq!(sampler.get(14), None);
+
+        // Every string of length 2 should be picked about equally often
+        let sampler = Sampler::new(dfa, 2..=2);
+        let mut seed = 7u64;
+        let mut random = || {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed
+        };
+        let mut counts = std::collections::HashMap::new();
+        for _ in 0..6000 {
+            *counts.entry(sampler.sample(&mut random).unwrap()).or_insert(0) += 1;
+        }
+        assert_eq!(counts.len(), 8);
+        assert!(counts.values().all(|c| (600..900).contains(c)), "{counts:?}");
+
+        assert!(Sampler::new(to_automata(to_regex("a{5}")), 0..5).sample(random).is_none());
+    }
+
+    #[cfg(feature = "proptest")]
+    proptest::proptest! {
+        #[test]
+        fn sampled_strategy(s in Sampler::new(to_automata(to_regex("[0-9]{3}-[a-z]+")), 4..12).strategy()) {
+            assert!(regex!("[0-9]{3}-[a-z]+").test(&s));
+        }
+    }
+
+    #[test]
+    fn language_statistics() {
+        let dfa = to_automata(to_regex("ab{2,4}(c|d€)?"));
+        assert_eq!((dfa.min_len(), dfa.max_len()), (Some(3), Some(7)));
+        assert_eq!((dfa.min_byte_len(), dfa.max_byte_len()), (Some(3), Some(9)));
+        assert!(dfa.is_finite());
+        assert_eq!((0..8).map(|l| dfa.count_of_length(l)).collect::<Vec<_>>(), [0, 0, 0, 1, 2, 3, 2, 1]);
+
+        let dfa = to_automata(to_regex("x+[0-9]"));
+        assert_eq!((dfa.min_len(), dfa.max_len()), (Some(2), None));
+        assert!(!dfa.is_finite());
+        assert_eq!(dfa.count_of_length(4), 10);
+
+        let empty = to_automata(to_regex("[a-z]")).intersection(&to_automata(to_regex("[0-9]")));
+        assert_eq!((empty.min_len(), empty.max_len()), (None, None));
+        assert!(empty.is_finite());
+
+        // A transition matching no characters doesn't count towards the lengths
+        let nothing = TransitionType::ExcludeRange(0, const_regex_util::char_to_utf8(char::MAX));
+        let dfa = DFA { transitions: vec![(false, vec![(nothing, 1), (TransitionType::Single('a' as u32), 1)]), (true, vec![])] };
+        assert_eq!((dfa.min_byte_len(), dfa.max_byte_len()), (Some(1), Some(1)));
+        assert_eq!((dfa.min_len(), dfa.max_len()), (Some(1), Some(1)));
+
+        let r = regex!("[0-9]{3}-[0-9]{2,4}");
+        assert_eq!((r.min_byte_len(), r.max_byte_len()), (6, 8));
+        assert!(!r.test("123-12345"));
+        assert!(r.test("123-1234"));
+        assert_eq!(regex!("a+").max_byte_len(), usize::MAX);
+        assert_eq!(regex!("€").min_byte_len(), 3);
+    }
+
+    #[test]
+    fn ranking() {
+        let codes = to_automata(to_regex("[A-C][0-9]{2}|X"));
+        assert_eq!(rank(&codes, "X"), Some(0));
+        assert_eq!(rank(&codes, "A00"), Some(1));
+        assert_eq!(rank(&codes, "A42"), Some(43));
+        assert_eq!(rank(&codes, "C99"), Some(300));
+        assert_eq!(rank(&codes, "D00"), None);
+        assert_eq!(rank(&codes, "A0"), None);
+        for n in [0, 1, 43, 150, 300] {
+            assert_eq!(rank(&codes, &unrank(&codes, n)), Some(n));
+        }
+        assert_eq!(unrank(&codes, 101), "B00");
+
+        let words = to_automata(to_regex("[a-b]+"));
+        assert_eq!((0..7).map(|n| unrank(&words, n)).collect::<Vec<_>>(), ["a", "b", "aa", "ab", "ba", "bb", "aaa"]);
+        assert_eq!(rank(&words, &"b".repeat(40)), Some((1 << 41) - 3));
+        assert_eq!(unrank(&words, (1 << 41) - 3), "b".repeat(40));
+        assert!(std::panic::catch_unwind(|| unrank(&words, u128::MAX)).is_err());
+        assert!(std::panic::catch_unwind(|| unrank(&codes, u128::MAX)).is_err());
+
+        let surrogates = to_automata(to_regex("[\u{D7FF}-\u{E000}]"));
+        assert_eq!(unrank(&surrogates, 1), "\u{E000}");
+        assert_eq!(rank(&surrogates, "\u{E000}"), Some(1));
+    }
+
+    #[test]
+    fn shortlex_strings() {
+        let keywords = to_automata(to_regex("if|else|elif|for|fn"));
+        assert_eq!(keywords.strings(None).collect::<Vec<_>>(), ["fn", "if", "for", "elif", "else"]);
+        assert_eq!(keywords.strings(Some(2)).collect::<Vec<_>>(), ["fn", "if"]);
+
+        let dfa = to_automata(to_regex("(ab)*"));
+        assert_eq!(dfa.strings(None).take(4).collect::<Vec<_>>(), ["", "ab", "abab", "ababab"]);
+        assert_eq!(dfa.strings(Some(5)).count(), 3);
+
+        let dfa = to_automata(to_regex("[0-9]{1,3}"));
+        assert_eq!(dfa.strings(None).count(), 1110);
+        assert!(dfa.strings(None).all(|s| dfa.test(&s)));
+
+        let empty = keywords.intersection(&dfa);
+        assert_eq!(empty.strings(None).next(), None);
+    }
+
+    #[test]
+    fn state_elimination() {
+        for p in ["a*", "(ab)+", "a?b{1,2}", "(a|b)*abb", "[^b]*(bc)?", "((a|b)?c*)+x", "ab|ac|x.z", "[0-9]{2,3}-?€"] {
+            let dfa = to_automata(to_regex(p));
+            let regex = dfa.to_ast().unwrap().to_string();
+            assert_eq!(is_equivalent(&dfa, &to_automata(to_regex(&regex))), Ok(()), "{p} -> {regex}");
+        }
+
+        let dfa = |p: &str| to_automata(to_regex(p));
+        assert_eq!(dfa("abc|abd|abe").to_ast().unwrap().to_string(), "ab[c-e]");
+        assert_eq!(dfa("(a|b)(a|b)*").to_ast().unwrap().to_string(), "[a-b]+");
+        assert_eq!(dfa("[a-z]+").intersection(&dfa(".{2}")).to_ast().unwrap().to_string(), "[a-z]{2}");
+        assert!(dfa("a").intersection(&dfa("b")).to_ast().is_none());
+
+        let dfa = dfa("[A-Z]+").minimize();
+        assert_eq!(dfa.transitions.len(), 2);
+    }
+
+    #[test]
+    fn dot_output() {
+        let dfa = to_automata(to_regex("[a-z_]+\\n?|[^\n\"]"));
+        let dot = dfa.to_dot();
+        assert!(dot.starts_with("digraph DFA {"), "{dot}");
+        assert!(dot.contains("start -> 0;"), "{dot}");
+        assert!(dot.contains("[label=\"_, a-z\"];"), "{dot}");
+        assert!(dot.contains("0 -> 1 [label=\"^\\\\n, \\\", _, a-z\"];"), "{dot}");
+        assert!(dot.contains("[label=\"\\\\n\"];"), "{dot}");
+        assert_eq!(dot.matches("doublecircle").count(), dfa.transitions.iter().filter(|(s, _)| *s).count());
+
+        // Excluded ranges next to the surrogates
+        let dot = to_automata(to_regex("[^\u{E000}-\u{E005}]")).to_dot();
+        assert!(dot.contains("[label=\"^\u{E000}-\u{E005}\"];"), "{dot}");
+        let dot = to_automata(to_regex("[^\u{D7FF}]")).to_dot();
+        assert!(dot.contains("[label=\"^\u{D7FF}\"];"), "{dot}");
+
+        let dot = to_nfa(to_regex("a*b")).to_dot();
+        assert!(dot.starts_with("digraph NFA {"), "{dot}");
+        assert!(dot.contains("[label=\"ε\"]"), "{dot}");
+        assert!(dot.contains("[label=\"a\"]"), "{dot}");
+        assert_eq!(dot.matches("doublecircle").count(), 1);
+    }
+
+    #[test]
+    fn serialisation() {
+        let dfa = to_automata(to_regex("[^a-y]b|zc|€+"));
+        let bytes = dfa.to_bytes();
+        let loaded = DFA::from_bytes(&bytes).unwrap();
+        assert_eq!(is_equivalent(&dfa, &loaded), Ok(()));
+        assert_eq!(loaded.to_bytes(), bytes);
+
+        let mut corrupt = bytes.clone();
+        *corrupt.last_mut().unwrap() ^= 1;
+        assert_eq!(DFA::from_bytes(&corrupt).unwrap_err(), DecodeError::ChecksumMismatch);
+        assert_eq!(DFA::from_bytes(&bytes[..bytes.len() - 1]).unwrap_err(), DecodeError::Truncated);
+        assert_eq!(DFA::from_bytes(b"nope").unwrap_err(), DecodeError::BadMagic);
+        let mut future = bytes.clone();
+        future[4] = 2;
+        assert_eq!(DFA::from_bytes(&future).unwrap_err(), DecodeError::UnsupportedVersion(2));
+
+        // Checked in so that format changes are noticed
+        let expected = to_automata(to_regex("#([0-9a-fA-F]{3}){1,2}"));
+        let fixture = DFA::from_bytes(include_bytes!("../test_data/hex_colour.dfa")).unwrap();
+        assert_eq!(is_equivalent(&expected, &fixture), Ok(()));
+
+        let hex = include_dfa!("test_data/hex_colour.dfa");
+        assert!(hex.test("#fA0") && hex.test("#00ff00"));
+        assert!(!hex.test("#00ff0") && !hex.test("00ff00"));
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn json_serialisation() {
+        let dfa = to_automata(to_regex("[^a-y]b|zc|€+"));
+        assert_eq!(is_equivalent(&dfa, &DFA::from_json(&dfa.to_json()).unwrap()), Ok(()));
+        assert!(include_dfa!("test_data/hex_colour.json").test("#abc"));
+    }
+
+    #[test]
+    fn regex_sets() {
+        const HEX: [bool; 3] = regex_set!["[0-9]+", "[a-z]+", "0x[0-9a-f]+"].matches("0x1f");
+        assert_eq!(HEX, [false, false, true]);
+
+        let set = regex_set!["[0-9]+", "[a-z]+", "0x[0-9a-f]+", "[0-9a-f]+"];
+        assert_eq!(set.matches("123"), [true, false, false, true]);
+        assert_eq!(set.matches("0x1f"), [false, false, true, false]);
+        assert_eq!(set.matches("cafe"), [false, true, false, true]);
+        assert_eq!(set.matches("?"), [false; 4]);
+        assert_eq!(set.first_match("beef"), Some(1));
+        assert_eq!(set.first_match("x"), Some(1));
+        assert_eq!(set.first_match("0x"), None);
+        assert!(set.test("42") && !set.test(""));
+
+        let set = try_to_set(["a+", "ab", "b"].map(to_regex), &CompileOptions::default()).unwrap();
+        assert_eq!(set.matches("ab").iter().collect::<Vec<_>>(), [1]);
+        assert_eq!(set.first_match("aaa"), Some(0));
+        assert!(set.matches("ba").is_empty());
+        assert_eq!(set.dfa.test("b"), set.first_match("b").is_some());
+    }
+
+    #[test]
+    fn lexers() {
+        lexer! {
+            enum Token {
+                Let = "let",
+                Ident = "[a-z_][a-z_0-9]*",
+                Number = "[0-9]+(\\.[0-9]+)?",
+                Eq = "=",
+                EqEq = "==",
+                #[skip]
+                Whitespace = "[ \t\n]+",
+            }
+        }
+
+        let tokens = Token::lex("let letter == 1.5\nx=2").collect::<Vec<_>>();
+        assert_eq!(tokens, [
+            (Token::Let, 0..3),
+            (Token::Ident, 4..10),
+            (Token::EqEq, 11..13),
+            (Token::Number, 14..17),
+            (Token::Ident, 18..19),
+            (Token::Eq, 19..20),
+            (Token::Number, 20..21),
+        ]);
+
+        let mut lexer = Token::lex("x = 1 ? 2");
+        assert_eq!(lexer.by_ref().count(), 3);
+        assert_eq!(lexer.remainder(), "? 2");
+
+        const FIRST: Option<(Token, usize)> = Token::next_token("12.5e3", 0);
+        assert_eq!(FIRST, Some((Token::Number, 4)));
+        assert_eq!(Token::next_token("12.", 0), Some((Token::Number, 2)));
+        assert_eq!(Token::next_token("€", 0), None);
+        assert_eq!(Token::next_token("x€1", 2), None);
+        assert_eq!(Token::next_token("x1", 3), None);
+        assert_eq!(Token::next_token("x1", 2), None);
+        assert_eq!(Token::next_token("x 1", 1), Some((Token::Whitespace, 2)));
+
+        let set = try_to_set(["a", "a+", "ab"].map(to_regex), &CompileOptions::default()).unwrap();
+        assert_eq!(set.longest_match("aab"), Some((1, 2)));
+        assert_eq!(set.longest_match("abc"), Some((2, 2)));
+        assert_eq!(set.longest_match("a"), Some((0, 1)));
+        assert_eq!(set.longest_match("b"), None);
+    }
+
+    #[test]
+    fn prefixes() {
+        const END: Option<usize> = regex!("[0-9]+(\\.[0-9]+)?").longest_prefix("3.14.15");
+        assert_eq!(END, Some(4));
+
+        let number = regex!("[0-9]+(\\.[0-9]+)?");
+        assert_eq!(number.shortest_prefix("3.14.15"), Some(1));
+        assert_eq!(number.longest_prefix("12."), Some(2));
+        assert_eq!(number.longest_prefix("x12"), None);
+
+        let optional = regex!("(ab)*");
+        assert_eq!(optional.longest_prefix("ababa"), Some(4));
+        assert_eq!(optional.shortest_prefix("ababa"), Some(0));
+        assert_eq!(regex!("€+").longest_prefix("€€e"), Some(6));
+
+        let dfa = to_automata(to_regex("a+b?"));
+        assert_eq!(dfa.longest_prefix("aaabb"), Some(4));
+        assert_eq!(dfa.shortest_prefix("aaabb"), Some(1));
+        assert_eq!(dfa.longest_prefix("ba"), None);
+    }
+
+    #[test]
+    fn suffixes() {
+        const ENDINGS: [bool; 2] = {
+            let source = regex!("\\.(rs|toml)");
+            [source.ends_with_match("src/lib.rs"), source.ends_with_match("Cargo.lock")]
+        };
+        assert_eq!(ENDINGS, [true, false]);
+
+        let archive = regex!("\\.tar(\\.(gz|xz))?");
+        assert!(archive.ends_with_match("backup.tar.gz"));
+        assert!(archive.ends_with_match("backup.tar"));
+        assert!(!archive.ends_with_match("backup.tar.bz2"));
+        assert!(!archive.ends_with_match(""));
+
+        let number = regex!("[0-9]+");
+        assert_eq!(number.rfind("a1 b22 c333 d"), Some(10));
+        assert_eq!(number.rfind("a1 b22 c333"), Some(10));
+        assert_eq!(number.rfind("no digits"), None);
+        assert_eq!(regex!("€[0-9]").rfind("€1 €€2 x"), Some(8));
+        assert_eq!(regex!("a*").rfind("baa"), Some(3));
+
+        // The DFA for strings ending in a match needs a state for every combination of the last 11 characters
+        let tail = regex!("a[ab]{10}");
+        assert!(tail.ends_with_match("xabbbbbbbbbb"));
+        assert!(!tail.ends_with_match("xbbbbbbbbbbb"));
+        assert_eq!(tail.rfind("aaaaaaaaaaaa b"), Some(1));
+
+        assert_eq!(const_regex_util::prev_char("a€", 4), Some((const_regex_util::char_to_utf8('€'), 1)));
+        assert_eq!(const_regex_util::prev_char("a€", 1), Some((const_regex_util::char_to_utf8('a'), 0)));
+        assert_eq!(const_regex_util::prev_char("a€", 0), None);
+        assert_eq!(const_regex_util::prev_char("a€", 2), None);
+        assert_eq!(const_regex_util::prev_char("a€", 5), None);
+
+        // Reversing this needs a state for every combination of the last 10 characters
+        let dfa = to_automata(to_regex("[ab]{10}a.*"));
+        assert!(matches!(dfa.try_reverse(1000), Err(CompileError::TooManyDfaStates { limit: 1000 })));
+        assert!(dfa.try_reverse(5000).unwrap().test("bababbbbbbbbbb"));
+        let options = CompileOptions { max_dfa_states: 1000, ..CompileOptions::default() };
+        assert!(dfa.try_to_tokens(&options).is_err());
+    }
+
+    #[test]
+    fn viable_prefixes() {
+        const VIABLE: [bool; 2] = {
+            let date = regex!("[0-9]{4}-[0-9]{2}-[0-9]{2}");
+            [date.is_viable_prefix("2024-0"), date.is_viable_prefix("2024-x")]
+        };
+        assert_eq!(VIABLE, [true, false]);
+
+        let date = regex!("[0-9]{4}-[0-9]{2}-[0-9]{2}");
+        assert!(date.is_viable_prefix(""));
+        assert!(date.is_viable_prefix("2024-01-31"));
+        assert!(!date.is_viable_prefix("2024-01-311"));
+        assert_eq!(date.next_allowed("2024"), ['-'..='-']);
+        assert_eq!(date.next_allowed("20"), ['0'..='9']);
+        assert!(date.next_allowed("2024-01-31").is_empty());
+        assert!(date.next_allowed("x").is_empty());
+
+        let hex = regex!("0x[0-9a-fA-F]+|[0-9]+");
+        assert_eq!(hex.next_allowed("0"), ['0'..='9', 'x'..='x']);
+        assert_eq!(hex.next_allowed("0x"), ['0'..='9', 'A'..='F', 'a'..='f']);
+        assert_eq!(hex.next_allowed(""), ['0'..='9']);
+
+        // State 2 can never reach an accepting state
+        let single = |c| TransitionType::Single(const_regex_util::char_to_utf8(c));
+        let dfa = DFA { transitions: vec![(false, vec![(single('a'), 1), (single('b'), 2)]), (true, vec![]), (false, vec![])] };
+        assert!(dfa.is_viable_prefix("") && dfa.is_viable_prefix("a"));
+        assert!(!dfa.is_viable_prefix("b") && !dfa.is_viable_prefix("c"));
+        assert_eq!(dfa.next_allowed(""), ['a'..='a']);
+        assert!(dfa.next_allowed("b").is_empty());
+        assert_eq!(to_automata(to_regex("[^b-y]")).next_allowed(""), ['\0'..='a', 'z'..=char::MAX]);
+    }
 }